@@ -0,0 +1,62 @@
+use crate::config::{AxisCalibration, SimAxis};
+use crate::sim::SimClientEvent;
+
+/// Largest magnitude of the value of a simulator axis event.
+const SIM_AXIS_RANGE: f64 = 16383.0;
+
+impl AxisCalibration {
+    /// Map a raw ADC value to the range of the simulator axis events from -16383 to 16383.
+    pub fn apply(&self, raw: i32) -> i32 {
+        let position = match self.center {
+            Some(center) => {
+                // Both halves of a centered axis are scaled separately, so the detent maps to zero
+                let position = if raw < center {
+                    -fraction(raw, center, self.min)
+                } else {
+                    fraction(raw, center, self.max)
+                };
+                let position = if self.invert { -position } else { position };
+                let magnitude = snap(position.abs(), self.deadzone, 0.0);
+                position.signum() * magnitude.powf(self.curve)
+            }
+            None => {
+                let position = fraction(raw, self.min, self.max);
+                let position = if self.invert {
+                    1.0 - position
+                } else {
+                    position
+                };
+                let position = snap(position, self.deadzone, self.deadzone);
+                2.0 * position.powf(self.curve) - 1.0
+            }
+        };
+        (position * SIM_AXIS_RANGE).round() as i32
+    }
+
+    /// Simulator event that sets the axis to the calibrated value.
+    pub fn event(&self, value: i32) -> SimClientEvent {
+        match self.axis {
+            SimAxis::Throttle => SimClientEvent::ThrottleAxisSet(value),
+            SimAxis::Mixture => SimClientEvent::MixtureAxisSet(value),
+            SimAxis::Propeller => SimClientEvent::PropellerAxisSet(value),
+            SimAxis::ElevatorTrim => SimClientEvent::ElevatorTrimAxisSet(value),
+        }
+    }
+}
+
+/// Position of `raw` between `from` and `to` as a fraction from 0.0 to 1.0.
+fn fraction(raw: i32, from: i32, to: i32) -> f64 {
+    if from == to {
+        return 0.0;
+    }
+    ((raw - from) as f64 / (to - from) as f64).clamp(0.0, 1.0)
+}
+
+/// Snap the lower and upper end of a fraction to 0.0 and 1.0 and rescale the remaining travel.
+fn snap(position: f64, lower: f64, upper: f64) -> f64 {
+    let travel = 1.0 - lower - upper;
+    if travel <= 0.0 {
+        return 0.0;
+    }
+    ((position - lower) / travel).clamp(0.0, 1.0)
+}
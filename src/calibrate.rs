@@ -0,0 +1,100 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::config::{AxisCalibration, Config, SimAxis};
+use crate::panels::eventsim::EventSimPanel;
+
+/// Positions the pilot is asked to move an axis to, in the order of the wizard.
+const STEPS: &[&str] = &[
+    "Move the axis to its minimum position and press Enter",
+    "Move the axis to its maximum position and press Enter",
+    "Move the axis to its center detent and press Enter, or type 'n' and Enter if it has none",
+];
+
+/// Interactively record the travel of a panel axis and store its calibration in the config file.
+pub fn calibrate(
+    config: &Config,
+    config_path: impl AsRef<Path>,
+    panel_name: &str,
+    axis_name: &str,
+) -> Result<AxisCalibration, Box<dyn std::error::Error>> {
+    let port = config
+        .panel_port(panel_name)
+        .ok_or_else(|| format!("No panel named '{panel_name}' in the configuration"))?;
+    if panel_name != "eventsim" {
+        return Err(format!("Panel '{panel_name}' has no analog axes").into());
+    }
+
+    // Keep the settings of an existing calibration and only replace the recorded positions
+    let mut calibration = match config.panel_axes(panel_name).remove(axis_name) {
+        Some(calibration) => calibration,
+        None => AxisCalibration {
+            axis: sim_axis(axis_name)?,
+            min: 0,
+            max: 0,
+            center: None,
+            deadzone: 0.0,
+            curve: 1.0,
+            invert: false,
+        },
+    };
+
+    // Read the confirmations of the pilot without blocking the panel
+    let (input_tx, input_rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            if input_tx.send(line.unwrap_or_default()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let (hw_tx, _) = mpsc::channel();
+    let (_, sim_rx) = mpsc::channel();
+    let mut panel = EventSimPanel::new(&port, config, hw_tx, sim_rx);
+
+    let mut positions = Vec::new();
+    let mut current = None;
+    println!("{}", STEPS[0]);
+    panel.stream_axis(axis_name, |value| {
+        if let Some(value) = value {
+            current = Some(value);
+            print!("\rRaw value: {value:>6}");
+            let _ = io::stdout().flush();
+        }
+        if let Ok(input) = input_rx.try_recv() {
+            let position = match input.trim() {
+                "n" | "N" if positions.len() == 2 => None,
+                _ if current.is_none() => {
+                    println!("No value received for axis '{axis_name}' yet");
+                    return true;
+                }
+                _ => current,
+            };
+            positions.push(position);
+            if let Some(step) = STEPS.get(positions.len()) {
+                println!("{step}");
+            }
+        }
+        positions.len() < STEPS.len()
+    })?;
+
+    calibration.min = positions[0].expect("recorded minimum");
+    calibration.max = positions[1].expect("recorded maximum");
+    calibration.center = positions[2];
+    Config::write_axis_calibration(config_path, panel_name, axis_name, &calibration)?;
+    Ok(calibration)
+}
+
+/// Simulator axis for a new calibration, derived from the axis name like `THROTTLE` or `ELEVATOR_TRIM`.
+fn sim_axis(axis_name: &str) -> Result<SimAxis, String> {
+    toml::Value::String(axis_name.to_lowercase().replace('_', "-"))
+        .try_into()
+        .map_err(|_| {
+            format!(
+                "Cannot tell which simulator control axis '{axis_name}' drives, add it with an 'axis' to the configuration first"
+            )
+        })
+}
@@ -1,11 +1,27 @@
-use std::{collections::HashMap, fs, path::Path};
+use std::{collections::HashMap, fs, path::Path, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
+use crate::simvar::SimVar;
+
+/// Time in milliseconds to wait for a panel to become ready after a reset.
+const DEFAULT_READY_TIMEOUT_MS: u64 = 2000;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub log_level: log::LevelFilter,
+    #[serde(default)]
+    pub flaps: FlapDetents,
+    #[serde(default)]
+    pub reconcile: ReconcilePolicy,
+    #[serde(default)]
+    pub gear: GearWarning,
+    /// Smallest changes of float SimVars that send the aircraft state to the panels, by SimVar.
+    #[serde(default)]
+    pub epsilons: HashMap<SimVar, f64>,
     panels: HashMap<String, Panel>,
+    #[serde(default)]
+    profiles: HashMap<String, AircraftProfile>,
 }
 
 impl Config {
@@ -17,18 +33,456 @@ impl Config {
         Ok(config)
     }
 
+    /// Store the calibration of a panel axis in the config file, keeping all other settings and comments intact.
+    pub fn write_axis_calibration(
+        path: impl AsRef<Path>,
+        panel: &str,
+        axis: &str,
+        calibration: &AxisCalibration,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let config_content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to open config file '{}': {e}", path.display()))?;
+        let mut document: toml_edit::DocumentMut = config_content
+            .parse()
+            .map_err(|e| format!("Failed to parse configuration: {e}"))?;
+        let calibration: toml_edit::DocumentMut = toml::to_string(calibration)?.parse()?;
+
+        let axes = document
+            .get_mut("panels")
+            .and_then(|panels| panels.get_mut(panel))
+            .and_then(toml_edit::Item::as_table_mut)
+            .ok_or_else(|| format!("No panel named '{panel}' in the configuration"))?
+            .entry("axes")
+            .or_insert_with(|| {
+                let mut axes = toml_edit::Table::new();
+                axes.set_implicit(true);
+                toml_edit::Item::Table(axes)
+            })
+            .as_table_mut()
+            .ok_or_else(|| format!("The axes of panel '{panel}' are not a table"))?;
+        axes.insert(axis, toml_edit::Item::Table(calibration.as_table().clone()));
+        fs::write(path, document.to_string())
+            .map_err(|e| format!("Failed to write config file '{}': {e}", path.display()))?;
+        Ok(())
+    }
+
     pub fn eventsim_port(&self) -> Option<String> {
-        self.panels.get("eventsim").map(|panel| panel.port.clone())
+        self.panel_port("eventsim")
     }
 
     pub fn airspeedindicator_port(&self) -> Option<String> {
+        self.panel_port("airspeedindicator")
+    }
+
+    pub fn autopilot_port(&self) -> Option<String> {
+        self.panel_port("autopilot")
+    }
+
+    pub fn radio_port(&self) -> Option<String> {
+        self.panel_port("radio")
+    }
+
+    pub fn transponder_port(&self) -> Option<String> {
+        self.panel_port("transponder")
+    }
+
+    pub fn panel_port(&self, name: &str) -> Option<String> {
+        self.panels.get(name).map(|panel| panel.port.clone())
+    }
+
+    /// Reset behaviour of the panel after its serial port was opened.
+    pub fn panel_reset(&self, name: &str) -> ResetConfig {
+        let panel = self.panels.get(name);
+        ResetConfig {
+            strategy: panel.map(|panel| panel.reset).unwrap_or_default(),
+            ready_timeout: Duration::from_millis(
+                panel
+                    .and_then(|panel| panel.ready_timeout_ms)
+                    .unwrap_or(DEFAULT_READY_TIMEOUT_MS),
+            ),
+        }
+    }
+
+    /// Calibration of the analog axes of the panel by axis name.
+    pub fn panel_axes(&self, name: &str) -> HashMap<String, AxisCalibration> {
+        self.panels
+            .get(name)
+            .map(|panel| panel.axes.clone())
+            .unwrap_or_default()
+    }
+
+    /// Rotary encoders of the panel by encoder name.
+    pub fn panel_encoders(&self, name: &str) -> HashMap<String, EncoderConfig> {
         self.panels
-            .get("airspeedindicator")
-            .map(|panel| panel.port.clone())
+            .get(name)
+            .map(|panel| panel.encoders.clone())
+            .unwrap_or_default()
+    }
+
+    /// Baud rate of the bootloader used when flashing the panel firmware.
+    pub fn panel_bootloader_baud_rate(&self, name: &str) -> Option<u32> {
+        self.panels
+            .get(name)
+            .and_then(|panel| panel.bootloader_baud_rate)
+    }
+
+    /// Channel spacing the COM radios of the panel are tuned with.
+    pub fn panel_com_spacing(&self, name: &str) -> ChannelSpacing {
+        self.panels
+            .get(name)
+            .map(|panel| panel.com_spacing)
+            .unwrap_or_default()
+    }
+
+    /// Names of the panels that drive stepper gauges, in alphabetical order.
+    pub fn gauge_panels(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .panels
+            .iter()
+            // The airspeed indicator has a panel type of its own that only uses its gauge for the calibration
+            .filter(|(name, panel)| *name != "airspeedindicator" && !panel.gauges.is_empty())
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Name the firmware of the panel announces itself with, which defaults to the panel name.
+    pub fn panel_device(&self, name: &str) -> String {
+        self.panels
+            .get(name)
+            .and_then(|panel| panel.device.clone())
+            .unwrap_or_else(|| name.into())
+    }
+
+    /// Limits of all outputs of the panel.
+    pub fn panel_limits(&self, name: &str) -> OutputLimits {
+        self.panels
+            .get(name)
+            .map(|panel| panel.limits)
+            .unwrap_or_default()
+    }
+
+    /// Limits of individual outputs of the panel by channel, which take precedence over the limits of the panel.
+    pub fn panel_output_limits(&self, name: &str) -> HashMap<String, OutputLimits> {
+        self.panels
+            .get(name)
+            .map(|panel| panel.outputs.clone())
+            .unwrap_or_default()
+    }
+
+    /// Stepper gauges of the panel by the target name used in the frames.
+    pub fn panel_gauges(&self, name: &str) -> HashMap<String, GaugeConfig> {
+        self.panels
+            .get(name)
+            .map(|panel| panel.gauges.clone())
+            .unwrap_or_default()
+    }
+
+    /// Sources of the stepper gauges of the panel, including those of the gauges the aircraft profiles replace.
+    pub fn panel_gauge_sources(&self, name: &str) -> Vec<GaugeSource> {
+        let mut sources: Vec<GaugeSource> = self
+            .panel_gauges(name)
+            .values()
+            .chain(
+                self.profiles
+                    .values()
+                    .filter_map(|profile| profile.gauges.get(name))
+                    .flat_map(HashMap::values),
+            )
+            .map(|gauge| gauge.source)
+            .collect();
+        sources.sort_by_key(|source| *source as u8);
+        sources.dedup();
+        sources
+    }
+
+    /// Aircraft profiles with their names, in alphabetical order.
+    pub fn profiles(&self) -> Vec<(String, AircraftProfile)> {
+        let mut profiles: Vec<(String, AircraftProfile)> = self
+            .profiles
+            .iter()
+            .map(|(name, profile)| (name.clone(), profile.clone()))
+            .collect();
+        profiles.sort_by(|(a, _), (b, _)| a.cmp(b));
+        profiles
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Panel {
     port: String,
+    #[serde(default)]
+    reset: ResetStrategy,
+    ready_timeout_ms: Option<u64>,
+    bootloader_baud_rate: Option<u32>,
+    #[serde(default)]
+    axes: HashMap<String, AxisCalibration>,
+    #[serde(default)]
+    encoders: HashMap<String, EncoderConfig>,
+    #[serde(default)]
+    com_spacing: ChannelSpacing,
+    device: Option<String>,
+    #[serde(default)]
+    gauges: HashMap<String, GaugeConfig>,
+    #[serde(default)]
+    limits: OutputLimits,
+    #[serde(default)]
+    outputs: HashMap<String, OutputLimits>,
+}
+
+/// How a panel is reset after its serial port was opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResetStrategy {
+    /// Toggle DTR, which resets Arduino boards with an auto-reset circuit.
+    #[default]
+    Dtr,
+    /// Leave the board running, for boards without an auto-reset circuit.
+    None,
+}
+
+/// Spacing of the channels a COM radio can be tuned to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChannelSpacing {
+    #[default]
+    #[serde(rename = "25khz")]
+    Khz25,
+    #[serde(rename = "8.33khz")]
+    Khz833,
+}
+
+/// Reset behaviour of a panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResetConfig {
+    pub strategy: ResetStrategy,
+    /// Maximum time to wait for the banner or the handshake reply of the device after a reset.
+    pub ready_timeout: Duration,
+}
+
+/// How disagreements between the panel switches and the simulator are resolved after connecting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReconcilePolicy {
+    /// Send the switch positions to the simulator.
+    HardwareWins,
+    /// Keep the simulator state and indicate the disagreeing switches.
+    #[default]
+    SimWins,
+    /// Indicate the disagreeing switches and apply the switch positions when the pilot presses SYNC.
+    Prompt,
+}
+
+/// Mapping of the detents of a physical flap lever to the flap handle of the aircraft.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlapDetents {
+    /// Flap handle position of each lever detent in percent of the full travel, starting with retracted flaps.
+    pub positions: Vec<f64>,
+    /// Send the per-detent events `FLAPS_UP`, `FLAPS_1`, ... `FLAPS_DOWN` instead of `FLAPS_SET`.
+    ///
+    /// The simulator has events for the first three detents and the last one only, the others still use `FLAPS_SET`.
+    #[serde(default)]
+    pub detent_events: bool,
+}
+
+impl Default for FlapDetents {
+    fn default() -> Self {
+        Self {
+            positions: vec![0.0, 100.0 / 3.0, 200.0 / 3.0, 100.0],
+            detent_events: false,
+        }
+    }
+}
+
+/// Thresholds of the landing gear annunciation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GearWarning {
+    /// Warn if the gear is not down below this indicated airspeed in knots.
+    pub airspeed: Option<f64>,
+    /// Warn if the gear is not down below this altitude above ground in feet.
+    pub altitude: Option<f64>,
+    /// Time in milliseconds the gear may take to follow the lever before it is considered in disagreement.
+    #[serde(default = "default_transit_time_ms")]
+    pub transit_time_ms: u64,
+}
+
+fn default_transit_time_ms() -> u64 {
+    10000
+}
+
+impl Default for GearWarning {
+    fn default() -> Self {
+        Self {
+            airspeed: None,
+            altitude: None,
+            transit_time_ms: default_transit_time_ms(),
+        }
+    }
+}
+
+/// Simulator control driven by an analog axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SimAxis {
+    Throttle,
+    Mixture,
+    Propeller,
+    ElevatorTrim,
+}
+
+/// Calibration of an analog axis of a panel, which maps raw ADC values to the simulator range.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AxisCalibration {
+    /// Simulator control the axis drives.
+    pub axis: SimAxis,
+    /// Raw value at the lower end of the travel.
+    pub min: i32,
+    /// Raw value at the upper end of the travel.
+    pub max: i32,
+    /// Raw value at the center detent, for axes that are centered like trim.
+    pub center: Option<i32>,
+    /// Fraction of the travel around the center, or at the ends for uncentered axes, that is snapped.
+    #[serde(default)]
+    pub deadzone: f64,
+    /// Exponent of the response curve, where 1.0 is linear.
+    #[serde(default = "default_curve")]
+    pub curve: f64,
+    /// Reverse the direction of the axis.
+    #[serde(default)]
+    pub invert: bool,
+}
+
+fn default_curve() -> f64 {
+    1.0
+}
+
+/// Simulator setting adjusted by a rotary encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EncoderTarget {
+    HeadingBug,
+    Kohlsman,
+    ComWhole,
+    ComFraction,
+    AutopilotAltitude,
+    AutopilotVerticalSpeed,
+    AutopilotAirspeed,
+}
+
+/// Rotary encoder of a panel, which reports the number of detents it was turned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncoderConfig {
+    /// Simulator setting the encoder adjusts.
+    pub target: EncoderTarget,
+    /// Multipliers applied when the encoder is turned fast, in ascending order of their rate.
+    #[serde(default)]
+    pub acceleration: Vec<EncoderAcceleration>,
+    /// Number of steps from which a single event with the absolute value is sent instead of increments.
+    pub set_threshold: Option<u32>,
+}
+
+/// Multiplier of an encoder from a certain turning rate on.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EncoderAcceleration {
+    /// Turning rate in detents per second.
+    pub rate: f64,
+    /// Number of steps per detent.
+    pub multiplier: u32,
+}
+
+/// Limits of how often values are written to the outputs of a panel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutputLimits {
+    /// Maximum number of updates per second.
+    pub max_rate: Option<f64>,
+    /// Minimum change of a numeric value before it is written again.
+    pub deadband: Option<f64>,
+    /// Time in milliseconds after which a value is written again, even if it did not change enough.
+    pub max_staleness_ms: Option<u64>,
+}
+
+impl OutputLimits {
+    /// Fill the limits that are not set with those of `other`.
+    pub fn or(self, other: OutputLimits) -> Self {
+        Self {
+            max_rate: self.max_rate.or(other.max_rate),
+            deadband: self.deadband.or(other.deadband),
+            max_staleness_ms: self.max_staleness_ms.or(other.max_staleness_ms),
+        }
+    }
+}
+
+/// Simulator value a stepper gauge displays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GaugeSource {
+    Airspeed,
+    Altitude,
+    VerticalSpeed,
+    Heading,
+    TurnRate,
+    SlipBall,
+    Pitch,
+    Bank,
+}
+
+/// Stepper gauge of a panel that is driven with `Type<I-A>::Target<..>::Content<..>::Origin<Interface>;` frames.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GaugeConfig {
+    pub source: GaugeSource,
+    /// Content sent per unit of the simulator value, e.g. stepper steps per knot.
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    /// Content sent for a simulator value of zero.
+    #[serde(default)]
+    pub offset: f64,
+    /// Points `[value, content]` of a piecewise-linear calibration in any order of the value, which replaces
+    /// `scale` and `offset`. Values outside of the table are clamped to its first and last point.
+    #[serde(default)]
+    pub table: Vec<[f64; 2]>,
+    /// Time constant in seconds with which the needle follows the simulator value, zero disables the smoothing.
+    #[serde(default)]
+    pub smoothing: f64,
+    /// Maximum change of the content per second.
+    pub max_rate: Option<f64>,
+}
+
+impl GaugeConfig {
+    /// Gauge that is sent the simulator value as it is.
+    pub fn unscaled(source: GaugeSource) -> Self {
+        Self {
+            source,
+            scale: default_scale(),
+            offset: 0.0,
+            table: Vec::new(),
+            smoothing: 0.0,
+            max_rate: None,
+        }
+    }
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+/// Settings that apply while a certain aircraft is loaded, replacing those of the configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AircraftProfile {
+    /// Parts of the aircraft `TITLE` or `ATC MODEL` that select the profile, compared case-insensitively.
+    #[serde(rename = "match")]
+    pub matches: Vec<String>,
+    pub flaps: Option<FlapDetents>,
+    pub gear: Option<GearWarning>,
+    /// Simulator events sent for commands of the EventSim panel like `MISC1:1`, given as `<EVENT>` or
+    /// `<EVENT>:<data>`.
+    #[serde(default)]
+    pub events: HashMap<String, String>,
+    /// L:vars like `L:LIGHTING_TAXI_1` that replace the simulator state of EventSim channels like `MISC1`, by
+    /// channel.
+    #[serde(default)]
+    pub lvars: HashMap<String, String>,
+    /// Stepper gauges by panel and target, replacing the gauges of the panel configuration.
+    #[serde(default)]
+    pub gauges: HashMap<String, HashMap<String, GaugeConfig>>,
 }
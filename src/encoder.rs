@@ -0,0 +1,167 @@
+use std::time::Instant;
+
+use crate::config::{EncoderConfig, EncoderTarget};
+use crate::sim::{AircraftSimState, SimClientEvent};
+use crate::simvar::SimVar;
+
+/// Change of the altimeter setting in inches of mercury per `KOHLSMAN_INC`.
+const KOHLSMAN_STEP: f64 = 0.01;
+
+/// Conversion factor from inches of mercury to millibars.
+const MILLIBARS_PER_INHG: f64 = 33.8639;
+
+/// Change of the autopilot target altitude in feet per `AP_ALT_VAR_INC`.
+const ALTITUDE_STEP: i32 = 100;
+
+/// Highest autopilot target altitude in feet.
+const MAX_ALTITUDE: i32 = 99900;
+
+/// Change of the autopilot target vertical speed in feet per minute per `AP_VS_VAR_INC`.
+const VERTICAL_SPEED_STEP: i32 = 100;
+
+/// Largest autopilot target vertical speed in feet per minute.
+const MAX_VERTICAL_SPEED: i32 = 9900;
+
+impl EncoderTarget {
+    /// Events that increment and decrement the setting by one step.
+    fn step_events(&self) -> (SimClientEvent, SimClientEvent) {
+        match self {
+            EncoderTarget::HeadingBug => {
+                (SimClientEvent::HeadingBugInc, SimClientEvent::HeadingBugDec)
+            }
+            EncoderTarget::Kohlsman => (SimClientEvent::KohlsmanInc, SimClientEvent::KohlsmanDec),
+            EncoderTarget::ComWhole => (
+                SimClientEvent::ComRadioWholeInc,
+                SimClientEvent::ComRadioWholeDec,
+            ),
+            EncoderTarget::ComFraction => (
+                SimClientEvent::ComRadioFractInc,
+                SimClientEvent::ComRadioFractDec,
+            ),
+            EncoderTarget::AutopilotAltitude => (
+                SimClientEvent::AutopilotAltitudeInc,
+                SimClientEvent::AutopilotAltitudeDec,
+            ),
+            EncoderTarget::AutopilotVerticalSpeed => (
+                SimClientEvent::AutopilotVerticalSpeedInc,
+                SimClientEvent::AutopilotVerticalSpeedDec,
+            ),
+            EncoderTarget::AutopilotAirspeed => (
+                SimClientEvent::AutopilotAirspeedInc,
+                SimClientEvent::AutopilotAirspeedDec,
+            ),
+        }
+    }
+
+    /// SimVar holding the current value of the setting, if the encoder can set it directly.
+    fn sim_var(&self) -> Option<SimVar> {
+        match self {
+            EncoderTarget::HeadingBug => Some(SimVar::HeadingBug),
+            EncoderTarget::Kohlsman => Some(SimVar::Kohlsman),
+            EncoderTarget::AutopilotAltitude
+            | EncoderTarget::AutopilotVerticalSpeed
+            | EncoderTarget::AutopilotAirspeed => Some(SimVar::Autopilot),
+            EncoderTarget::ComWhole | EncoderTarget::ComFraction => None,
+        }
+    }
+
+    /// Event that sets the setting to its current value moved by the given number of steps, if the simulator has one.
+    fn set_event(&self, state: &AircraftSimState, steps: i32) -> Option<SimClientEvent> {
+        match self {
+            EncoderTarget::HeadingBug => {
+                let heading = (state.heading_bug.round() as i32 + steps).rem_euclid(360);
+                Some(SimClientEvent::HeadingBugSet(heading as u32))
+            }
+            EncoderTarget::Kohlsman => {
+                let setting =
+                    (state.kohlsman_setting + steps as f64 * KOHLSMAN_STEP).clamp(28.0, 31.0);
+                let millibars = setting * MILLIBARS_PER_INHG;
+                Some(SimClientEvent::KohlsmanSet(
+                    (millibars * 16.0).round() as u32
+                ))
+            }
+            EncoderTarget::AutopilotAltitude => {
+                let altitude = state.autopilot.altitude.round() as i32 + steps * ALTITUDE_STEP;
+                Some(SimClientEvent::AutopilotAltitudeSet(
+                    altitude.clamp(0, MAX_ALTITUDE) as u32,
+                ))
+            }
+            EncoderTarget::AutopilotVerticalSpeed => {
+                let vertical_speed =
+                    state.autopilot.vertical_speed.round() as i32 + steps * VERTICAL_SPEED_STEP;
+                Some(SimClientEvent::AutopilotVerticalSpeedSet(
+                    vertical_speed.clamp(-MAX_VERTICAL_SPEED, MAX_VERTICAL_SPEED),
+                ))
+            }
+            EncoderTarget::AutopilotAirspeed => {
+                let airspeed = (state.autopilot.airspeed.round() as i32 + steps).max(0);
+                Some(SimClientEvent::AutopilotAirspeedSet(airspeed as u32))
+            }
+            EncoderTarget::ComWhole | EncoderTarget::ComFraction => None,
+        }
+    }
+}
+
+/// Translates the detents of a rotary encoder into simulator events.
+#[derive(Debug)]
+pub struct Encoder {
+    config: EncoderConfig,
+    /// Time the encoder was last turned, used to determine the turning rate.
+    last_turn: Option<Instant>,
+}
+
+impl Encoder {
+    pub fn new(config: EncoderConfig) -> Self {
+        Self {
+            config,
+            last_turn: None,
+        }
+    }
+
+    /// SimVar the encoder reads to set the setting directly, if it has one.
+    pub fn sim_var(&self) -> Option<SimVar> {
+        self.config.target.sim_var()
+    }
+
+    /// Events for turning the encoder by the given number of detents, where positive numbers are clockwise.
+    pub fn turn(
+        &mut self,
+        detents: i32,
+        state: Option<&AircraftSimState>,
+        now: Instant,
+    ) -> Vec<SimClientEvent> {
+        // Accelerate based on the rate since the previous message of the encoder
+        let rate = self
+            .last_turn
+            .map(|last| {
+                detents.unsigned_abs() as f64 / now.duration_since(last).as_secs_f64().max(0.001)
+            })
+            .unwrap_or(0.0);
+        self.last_turn = Some(now);
+        let multiplier = self
+            .config
+            .acceleration
+            .iter()
+            .rev()
+            .find(|acceleration| rate >= acceleration.rate)
+            .map(|acceleration| acceleration.multiplier)
+            .unwrap_or(1);
+        let steps = detents * multiplier as i32;
+
+        // Bursts are more efficiently sent as a single absolute value
+        let burst = self
+            .config
+            .set_threshold
+            .is_some_and(|threshold| steps.unsigned_abs() >= threshold);
+        if let Some(event) = state
+            .filter(|_| burst)
+            .and_then(|state| self.config.target.set_event(state, steps))
+        {
+            return vec![event];
+        }
+
+        let (increment, decrement) = self.config.target.step_events();
+        let event = if steps >= 0 { increment } else { decrement };
+        vec![event; steps.unsigned_abs() as usize]
+    }
+}
@@ -0,0 +1,164 @@
+use std::fmt;
+
+/// Value of unprogrammed flash memory, used to fill gaps between records.
+pub const ERASED: u8 = 0xff;
+
+/// Address from which avr-gcc places the other memories in HEX files, e.g. the EEPROM at `0x810000` and the fuses at
+/// `0x820000`. Only the program flash lies below.
+const FLASH_END: u32 = 0x80_0000;
+
+/// Firmware image read from an Intel HEX file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareImage {
+    /// Address of the first byte of the image in flash memory.
+    pub base: u32,
+    /// Contiguous image content, gaps between records are filled with `0xff`.
+    pub data: Vec<u8>,
+}
+
+/// Errors while parsing an Intel HEX file.
+#[derive(Debug)]
+pub enum HexError {
+    /// A line of the file is not a valid record
+    InvalidRecord(usize, &'static str),
+    /// The checksum of a record does not match its content
+    Checksum(usize),
+    /// The file ended without an end-of-file record
+    MissingEof,
+    /// The file contains no data records
+    Empty,
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HexError::InvalidRecord(line, reason) => {
+                write!(f, "Invalid record on line {line}: {reason}")
+            }
+            HexError::Checksum(line) => write!(f, "Checksum mismatch on line {line}"),
+            HexError::MissingEof => write!(f, "Missing end-of-file record"),
+            HexError::Empty => write!(f, "File contains no data"),
+        }
+    }
+}
+
+impl std::error::Error for HexError {}
+
+impl FirmwareImage {
+    /// Parse the content of an Intel HEX file into a contiguous image of the program flash.
+    ///
+    /// Records of the EEPROM, fuses and lock bits are skipped, because the bootloader only writes the flash.
+    pub fn from_hex(content: &str) -> Result<Self, HexError> {
+        let mut chunks: Vec<(u32, Vec<u8>)> = Vec::new();
+        let mut upper_address = 0u32;
+        let mut eof = false;
+
+        for (index, line) in content.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if eof {
+                return Err(HexError::InvalidRecord(
+                    line_number,
+                    "record after end-of-file",
+                ));
+            }
+
+            let bytes = decode_record(line, line_number)?;
+            let length = bytes[0] as usize;
+            if bytes.len() != length + 5 {
+                return Err(HexError::InvalidRecord(line_number, "length mismatch"));
+            }
+            if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+                return Err(HexError::Checksum(line_number));
+            }
+            let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+            let data = &bytes[4..4 + length];
+
+            match bytes[3] {
+                // Data record
+                0x00 if upper_address + offset >= FLASH_END => {}
+                0x00 => chunks.push((upper_address + offset, data.to_vec())),
+                // End-of-file record
+                0x01 => eof = true,
+                // Extended segment address record
+                0x02 if length == 2 => {
+                    upper_address = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4
+                }
+                // Extended linear address record
+                0x04 if length == 2 => {
+                    upper_address = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16
+                }
+                // Start address records are irrelevant for AVR bootloaders
+                0x03 | 0x05 => {}
+                _ => {
+                    return Err(HexError::InvalidRecord(
+                        line_number,
+                        "unsupported record type",
+                    ))
+                }
+            }
+        }
+
+        if !eof {
+            return Err(HexError::MissingEof);
+        }
+
+        let base = chunks
+            .iter()
+            .map(|(address, _)| *address)
+            .min()
+            .ok_or(HexError::Empty)?;
+        let end = chunks
+            .iter()
+            .map(|(address, data)| *address + data.len() as u32)
+            .max()
+            .ok_or(HexError::Empty)?;
+        let mut image = vec![ERASED; (end - base) as usize];
+        for (address, data) in chunks {
+            let start = (address - base) as usize;
+            image[start..start + data.len()].copy_from_slice(&data);
+        }
+
+        Ok(Self { base, data: image })
+    }
+}
+
+/// Decode the hexadecimal digits of a record after the leading colon.
+fn decode_record(line: &str, line_number: usize) -> Result<Vec<u8>, HexError> {
+    let digits = line
+        .strip_prefix(':')
+        .ok_or(HexError::InvalidRecord(line_number, "missing start code"))?;
+    if digits.len() % 2 != 0 || digits.len() < 10 {
+        return Err(HexError::InvalidRecord(line_number, "truncated record"));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            digits
+                .get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or(HexError::InvalidRecord(line_number, "invalid hex digit"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_hex_skips_eeprom_records() {
+        let hex = "\
+:0400000001020304F2
+:02000004008179
+:0200000055AAFF
+:00000001FF
+";
+        let image = FirmwareImage::from_hex(hex).unwrap();
+        assert_eq!(image.base, 0);
+        assert_eq!(image.data, [1, 2, 3, 4]);
+    }
+}
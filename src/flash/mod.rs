@@ -0,0 +1,318 @@
+use core::fmt;
+use log::{debug, info};
+use std::thread;
+use std::time::Duration;
+
+use crate::panel::{Capabilities, Panel, PanelError};
+
+use ihex::ERASED;
+pub use ihex::{FirmwareImage, HexError};
+pub use stk500::Stk500;
+
+mod ihex;
+mod stk500;
+
+/// The baud rate of the Optiboot bootloader shipped with current Arduino boards.
+pub const DEFAULT_BOOTLOADER_BAUD_RATE: u32 = 115200;
+
+/// Errors related to flashing the firmware of a panel.
+#[derive(Debug)]
+pub enum FlashError {
+    /// Failed to open the serial port
+    SerialOpen(String, serialport::Error),
+    /// The firmware image could not be read
+    Hex(HexError),
+    /// The bootloader did not answer after the reset
+    NoSync,
+    /// The bootloader answered a command with an unexpected response
+    UnexpectedResponse(u8, Vec<u8>),
+    /// The microcontroller signature is not one we know how to program
+    UnsupportedDevice([u8; 3]),
+    /// The image ends at the given address beyond the flash memory of the microcontroller
+    ImageTooLarge(u32, u32),
+    /// The flash content differs from the image at the given address
+    Verify(u32),
+    /// The panel did not complete the handshake with the new firmware
+    Panel(PanelError),
+    /// Error that relates to the serial port
+    Serial(serialport::Error),
+    /// I/O error that wraps the standard error type
+    Io(std::io::Error),
+}
+
+impl fmt::Display for FlashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlashError::SerialOpen(port, e) => {
+                write!(f, "Failed to open serial port '{port}' for flashing: {e}")
+            }
+            FlashError::Hex(e) => write!(f, "Invalid firmware image: {e}"),
+            FlashError::NoSync => write!(f, "Bootloader did not respond after reset"),
+            FlashError::UnexpectedResponse(command, response) => write!(
+                f,
+                "Bootloader sent unexpected response {response:02x?} to command {command:#04x}"
+            ),
+            FlashError::UnsupportedDevice(signature) => {
+                write!(f, "Unsupported microcontroller signature {signature:02x?}")
+            }
+            FlashError::ImageTooLarge(end, flash_size) => write!(
+                f,
+                "Firmware image ends at {end:#06x}, beyond the {flash_size} bytes of flash memory"
+            ),
+            FlashError::Verify(address) => {
+                write!(f, "Verification failed at flash address {address:#06x}")
+            }
+            FlashError::Panel(e) => write!(f, "New firmware failed the handshake: {e}"),
+            FlashError::Serial(e) => write!(f, "Serial communication error: {}", e),
+            FlashError::Io(e) => write!(f, "Bootloader I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FlashError {}
+
+impl From<HexError> for FlashError {
+    fn from(value: HexError) -> Self {
+        Self::Hex(value)
+    }
+}
+
+impl From<PanelError> for FlashError {
+    fn from(value: PanelError) -> Self {
+        Self::Panel(value)
+    }
+}
+
+impl From<serialport::Error> for FlashError {
+    fn from(value: serialport::Error) -> Self {
+        Self::Serial(value)
+    }
+}
+
+impl From<std::io::Error> for FlashError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// Flash page size and flash size in bytes of the microcontroller with the given signature.
+fn flash_layout(signature: [u8; 3]) -> Option<(usize, u32)> {
+    match signature {
+        // ATmega328P and ATmega328 (Uno, Nano)
+        [0x1e, 0x95, 0x0f] | [0x1e, 0x95, 0x14] => Some((128, 32 * 1024)),
+        // ATmega168 (older Nano)
+        [0x1e, 0x94, 0x06] => Some((128, 16 * 1024)),
+        _ => None,
+    }
+}
+
+/// Upload a firmware image to a panel and confirm that the new firmware completes the handshake.
+///
+/// The board is reset into its bootloader by toggling DTR, the same way the panels reset the device when connecting.
+pub fn flash(
+    port: &str,
+    baud_rate: u32,
+    image: &FirmwareImage,
+    panel: &mut dyn Panel,
+) -> Result<Capabilities, FlashError> {
+    debug!("Resetting board on serial port {port} into bootloader");
+    let mut serial = serialport::new(port, baud_rate)
+        .timeout(Duration::from_millis(500))
+        .open()
+        .map_err(|e| FlashError::SerialOpen(port.into(), e))?;
+    serial.write_data_terminal_ready(false)?;
+    thread::sleep(Duration::from_millis(50));
+    serial.write_data_terminal_ready(true)?;
+    thread::sleep(Duration::from_millis(50));
+    serial.clear(serialport::ClearBuffer::All)?;
+
+    let mut programmer = Stk500::new(serial);
+    program(&mut programmer, image)?;
+    // Close the port so that the panel can open it for the handshake
+    drop(programmer);
+
+    info!("Verifying new firmware with panel handshake");
+    Ok(panel.probe()?)
+}
+
+/// Write and verify the image with an already reset bootloader.
+pub fn program<T: std::io::Read + std::io::Write>(
+    programmer: &mut Stk500<T>,
+    image: &FirmwareImage,
+) -> Result<(), FlashError> {
+    programmer.sync()?;
+    let signature = programmer.read_signature()?;
+    let (page_size, flash_size) =
+        flash_layout(signature).ok_or(FlashError::UnsupportedDevice(signature))?;
+    debug!("Bootloader reports signature {signature:02x?}");
+    let end = image.base + image.data.len() as u32;
+    if end > flash_size {
+        return Err(FlashError::ImageTooLarge(end, flash_size));
+    }
+
+    // Pages start on page boundaries, so an image that does not is padded at the start
+    let base = image.base - image.base % page_size as u32;
+    let mut data = vec![ERASED; (image.base - base) as usize];
+    data.extend_from_slice(&image.data);
+
+    programmer.enter_progmode()?;
+    let pages = data.chunks(page_size).zip((base..).step_by(page_size));
+
+    info!("Writing {} bytes of firmware", image.data.len());
+    for (page, address) in pages.clone() {
+        let mut page = page.to_vec();
+        page.resize(page_size, 0xff);
+        programmer.program_page(address, &page)?;
+    }
+
+    info!("Verifying {} bytes of firmware", image.data.len());
+    for (page, address) in pages {
+        let content = programmer.read_page(address, page.len())?;
+        if let Some(offset) = content.iter().zip(page).position(|(a, b)| a != b) {
+            return Err(FlashError::Verify(address + offset as u32));
+        }
+    }
+
+    programmer.leave_progmode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::io::{self, Read, Write};
+
+    /// Signature of the ATmega328P.
+    const ATMEGA328P: [u8; 3] = [0x1e, 0x95, 0x0f];
+
+    /// In-memory bootloader that answers STK500v1 commands like Optiboot.
+    struct Emulator {
+        flash: Vec<u8>,
+        /// Byte address of the next page command.
+        address: usize,
+        /// Byte addresses of the pages that were written.
+        written_pages: Vec<usize>,
+        input: Vec<u8>,
+        output: VecDeque<u8>,
+    }
+
+    impl Emulator {
+        fn new(flash_size: usize) -> Self {
+            Self {
+                flash: vec![ERASED; flash_size],
+                address: 0,
+                written_pages: Vec::new(),
+                input: Vec::new(),
+                output: VecDeque::new(),
+            }
+        }
+
+        /// Length of the command at the start of the input including `CRC_EOP`, once it is known.
+        fn command_len(&self) -> Option<usize> {
+            match *self.input.first()? {
+                // STK_LOAD_ADDRESS
+                0x55 => Some(4),
+                // STK_PROG_PAGE with the page size in big endian
+                0x64 => Some(
+                    5 + u16::from_be_bytes([*self.input.get(1)?, *self.input.get(2)?]) as usize,
+                ),
+                // STK_READ_PAGE
+                0x74 => Some(5),
+                _ => Some(2),
+            }
+        }
+
+        fn execute(&mut self, command: &[u8]) {
+            assert_eq!(command.last(), Some(&0x20), "command without CRC_EOP");
+            let mut response = vec![0x14];
+            match command[0] {
+                0x55 => self.address = u16::from_le_bytes([command[1], command[2]]) as usize * 2,
+                0x64 => {
+                    let page = &command[4..command.len() - 1];
+                    self.flash[self.address..self.address + page.len()].copy_from_slice(page);
+                    self.written_pages.push(self.address);
+                }
+                0x74 => {
+                    let length = u16::from_be_bytes([command[1], command[2]]) as usize;
+                    response.extend_from_slice(&self.flash[self.address..self.address + length]);
+                }
+                0x75 => response.extend_from_slice(&ATMEGA328P),
+                _ => {}
+            }
+            response.push(0x10);
+            self.output.extend(response);
+        }
+    }
+
+    impl Write for Emulator {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.input.extend_from_slice(buf);
+            while let Some(length) = self
+                .command_len()
+                .filter(|length| self.input.len() >= *length)
+            {
+                let command: Vec<u8> = self.input.drain(..length).collect();
+                self.execute(&command);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for Emulator {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let length = buf.len().min(self.output.len());
+            for (byte, response) in buf.iter_mut().zip(self.output.drain(..length)) {
+                *byte = response;
+            }
+            Ok(length)
+        }
+    }
+
+    fn image(base: u32, length: usize) -> FirmwareImage {
+        FirmwareImage {
+            base,
+            data: (0..length).map(|i| (i % 251) as u8).collect(),
+        }
+    }
+
+    #[test]
+    fn program_writes_and_verifies_image() {
+        let image = image(0, 300);
+        let mut programmer = Stk500::new(Emulator::new(32 * 1024));
+        program(&mut programmer, &image).unwrap();
+
+        let emulator = programmer.into_inner();
+        assert_eq!(&emulator.flash[..300], &image.data[..]);
+        assert!(emulator.flash[300..].iter().all(|byte| *byte == ERASED));
+        assert_eq!(emulator.written_pages, [0, 128, 256]);
+    }
+
+    #[test]
+    fn program_aligns_image_to_pages() {
+        let image = image(0x150, 100);
+        let mut programmer = Stk500::new(Emulator::new(32 * 1024));
+        program(&mut programmer, &image).unwrap();
+
+        let emulator = programmer.into_inner();
+        assert_eq!(emulator.written_pages, [0x100, 0x180]);
+        assert!(emulator.flash[0x100..0x150]
+            .iter()
+            .all(|byte| *byte == ERASED));
+        assert_eq!(&emulator.flash[0x150..0x1b4], &image.data[..]);
+    }
+
+    #[test]
+    fn program_rejects_image_beyond_flash() {
+        let image = image(32 * 1024 - 64, 128);
+        let mut programmer = Stk500::new(Emulator::new(32 * 1024));
+        assert!(matches!(
+            program(&mut programmer, &image),
+            Err(FlashError::ImageTooLarge(0x8040, 0x8000))
+        ));
+        assert!(programmer.into_inner().written_pages.is_empty());
+    }
+}
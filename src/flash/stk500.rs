@@ -0,0 +1,102 @@
+use log::debug;
+use std::io::{Read, Write};
+
+use super::FlashError;
+
+const STK_OK: u8 = 0x10;
+const STK_INSYNC: u8 = 0x14;
+const CRC_EOP: u8 = 0x20;
+const STK_GET_SYNC: u8 = 0x30;
+const STK_ENTER_PROGMODE: u8 = 0x50;
+const STK_LEAVE_PROGMODE: u8 = 0x51;
+const STK_LOAD_ADDRESS: u8 = 0x55;
+const STK_PROG_PAGE: u8 = 0x64;
+const STK_READ_PAGE: u8 = 0x74;
+const STK_READ_SIGN: u8 = 0x75;
+
+/// Memory type of the program flash in page commands.
+const MEMTYPE_FLASH: u8 = b'F';
+
+/// Number of sync attempts while the bootloader is starting up.
+const SYNC_ATTEMPTS: usize = 10;
+
+/// Programmer that talks the STK500v1 protocol spoken by the Arduino bootloaders.
+///
+/// The programmer works on any byte stream, so it can be driven by a serial port or a bootloader emulator.
+pub struct Stk500<T> {
+    port: T,
+}
+
+impl<T: Read + Write> Stk500<T> {
+    pub fn new(port: T) -> Self {
+        Self { port }
+    }
+
+    /// Give up the byte stream, e.g. to inspect the bootloader emulator.
+    #[cfg(test)]
+    pub fn into_inner(self) -> T {
+        self.port
+    }
+
+    /// Synchronize with the bootloader, which discards garbage left over from the reset.
+    pub fn sync(&mut self) -> Result<(), FlashError> {
+        for attempt in 1..=SYNC_ATTEMPTS {
+            match self.command(&[STK_GET_SYNC], 0) {
+                Ok(_) => return Ok(()),
+                Err(e) => debug!("Bootloader sync attempt {attempt} failed: {e}"),
+            }
+        }
+        Err(FlashError::NoSync)
+    }
+
+    /// Read the three byte device signature of the microcontroller.
+    pub fn read_signature(&mut self) -> Result<[u8; 3], FlashError> {
+        let signature = self.command(&[STK_READ_SIGN], 3)?;
+        Ok([signature[0], signature[1], signature[2]])
+    }
+
+    pub fn enter_progmode(&mut self) -> Result<(), FlashError> {
+        self.command(&[STK_ENTER_PROGMODE], 0).map(|_| ())
+    }
+
+    /// Leave programming mode, which makes the bootloader start the new firmware.
+    pub fn leave_progmode(&mut self) -> Result<(), FlashError> {
+        self.command(&[STK_LEAVE_PROGMODE], 0).map(|_| ())
+    }
+
+    /// Write a page of flash memory starting at the given byte address.
+    pub fn program_page(&mut self, address: u32, page: &[u8]) -> Result<(), FlashError> {
+        self.load_address(address)?;
+        let [size_high, size_low] = (page.len() as u16).to_be_bytes();
+        let mut command = vec![STK_PROG_PAGE, size_high, size_low, MEMTYPE_FLASH];
+        command.extend_from_slice(page);
+        self.command(&command, 0).map(|_| ())
+    }
+
+    /// Read a page of flash memory starting at the given byte address.
+    pub fn read_page(&mut self, address: u32, length: usize) -> Result<Vec<u8>, FlashError> {
+        self.load_address(address)?;
+        let [size_high, size_low] = (length as u16).to_be_bytes();
+        self.command(&[STK_READ_PAGE, size_high, size_low, MEMTYPE_FLASH], length)
+    }
+
+    /// Set the address for the next page command, which the bootloader expects in words.
+    fn load_address(&mut self, address: u32) -> Result<(), FlashError> {
+        let [low, high] = ((address / 2) as u16).to_le_bytes();
+        self.command(&[STK_LOAD_ADDRESS, low, high], 0).map(|_| ())
+    }
+
+    /// Send a command terminated by `CRC_EOP` and read a framed response with the given payload length.
+    fn command(&mut self, command: &[u8], response_len: usize) -> Result<Vec<u8>, FlashError> {
+        self.port.write_all(command)?;
+        self.port.write_all(&[CRC_EOP])?;
+        self.port.flush()?;
+
+        let mut response = vec![0; response_len + 2];
+        self.port.read_exact(&mut response)?;
+        if response[0] != STK_INSYNC || response[response_len + 1] != STK_OK {
+            return Err(FlashError::UnexpectedResponse(command[0], response));
+        }
+        Ok(response[1..=response_len].to_vec())
+    }
+}
@@ -0,0 +1,233 @@
+use std::time::{Duration, Instant};
+
+use crate::config::GearWarning;
+use crate::sim::{AircraftSimState, LandingGearStatus};
+
+/// State of a landing gear annunciator, encoded as the panels expect it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GearLight {
+    /// The gear is up and locked.
+    Off,
+    /// The gear is down and locked.
+    Green,
+    /// The gear is in transit or disagrees with the gear lever.
+    Red,
+}
+
+impl GearLight {
+    pub fn as_int(&self) -> i32 {
+        match self {
+            GearLight::Off => 0,
+            GearLight::Green => 1,
+            GearLight::Red => 2,
+        }
+    }
+}
+
+/// Annunciator states derived from the landing gear of the aircraft.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GearIndication {
+    pub front: GearLight,
+    pub left: GearLight,
+    pub right: GearLight,
+    /// The gear lever disagrees with the position of at least one gear leg.
+    pub disagreement: bool,
+    /// The gear is not down and locked while the aircraft is slow or low.
+    pub unsafe_warning: bool,
+}
+
+/// Derives gear annunciator states from the simulator state and the physical gear lever.
+#[derive(Debug)]
+pub struct GearLogic {
+    warning: GearWarning,
+    /// Time since which the lever and a gear leg disagree.
+    disagreement_since: Option<Instant>,
+}
+
+impl GearLogic {
+    pub fn new(warning: GearWarning) -> Self {
+        Self {
+            warning,
+            disagreement_since: None,
+        }
+    }
+
+    /// Evaluate the gear state, where `lever_down` is the position of the physical gear lever if it is known.
+    ///
+    /// Without a known lever position the legs are compared with the gear handle in the cockpit. Fixed gear is always
+    /// reported as down, so its annunciators stay dark.
+    pub fn update(
+        &mut self,
+        state: &AircraftSimState,
+        lever_down: Option<bool>,
+        now: Instant,
+    ) -> GearIndication {
+        if !state.gear_retractable {
+            self.disagreement_since = None;
+            return GearIndication {
+                front: GearLight::Off,
+                left: GearLight::Off,
+                right: GearLight::Off,
+                disagreement: false,
+                unsafe_warning: false,
+            };
+        }
+
+        let lever_down = lever_down.unwrap_or(state.gear_handle_down);
+        let legs = [
+            state.gear_center_state,
+            state.gear_left_state,
+            state.gear_right_state,
+        ];
+
+        // A leg that is not locked in the position of the lever disagrees once the gear had time to move, which also
+        // catches a leg stuck in transit
+        let lever_position = if lever_down {
+            LandingGearStatus::Down
+        } else {
+            LandingGearStatus::Up
+        };
+        let disagrees = |leg: LandingGearStatus| leg != lever_position;
+        if legs.iter().any(|leg| disagrees(*leg)) {
+            self.disagreement_since.get_or_insert(now);
+        } else {
+            self.disagreement_since = None;
+        }
+        let disagreement = self.disagreement_since.is_some_and(|since| {
+            now.duration_since(since) >= Duration::from_millis(self.warning.transit_time_ms)
+        });
+
+        let light = |leg: LandingGearStatus| match leg {
+            _ if disagreement && disagrees(leg) => GearLight::Red,
+            LandingGearStatus::Up => GearLight::Off,
+            LandingGearStatus::Down => GearLight::Green,
+            LandingGearStatus::InTransit | LandingGearStatus::Unknown => GearLight::Red,
+        };
+
+        let gear_down = legs.iter().all(|leg| *leg == LandingGearStatus::Down);
+        let slow = self
+            .warning
+            .airspeed
+            .is_some_and(|airspeed| state.airspeed < airspeed);
+        let low = self
+            .warning
+            .altitude
+            .is_some_and(|altitude| state.altitude_above_ground < altitude);
+
+        GearIndication {
+            front: light(state.gear_center_state),
+            left: light(state.gear_left_state),
+            right: light(state.gear_right_state),
+            disagreement,
+            unsafe_warning: !gear_down && !state.on_ground && (slow || low),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WARNING: GearWarning = GearWarning {
+        airspeed: Some(100.0),
+        altitude: Some(1000.0),
+        transit_time_ms: 10000,
+    };
+
+    /// Airborne aircraft with retractable gear whose legs are all in the given position.
+    fn state(legs: LandingGearStatus, handle_down: bool) -> AircraftSimState {
+        AircraftSimState {
+            gear_center_state: legs,
+            gear_left_state: legs,
+            gear_right_state: legs,
+            gear_retractable: true,
+            gear_handle_down: handle_down,
+            airspeed: 150.0,
+            altitude_above_ground: 5000.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn gear_in_transit_within_transit_time() {
+        let mut logic = GearLogic::new(WARNING);
+        let start = Instant::now();
+        let state = state(LandingGearStatus::InTransit, true);
+
+        logic.update(&state, Some(true), start);
+        let indication = logic.update(&state, Some(true), start + Duration::from_secs(9));
+
+        assert!(!indication.disagreement);
+        assert_eq!(indication.front, GearLight::Red);
+    }
+
+    #[test]
+    fn gear_disagrees_after_transit_time() {
+        let mut logic = GearLogic::new(WARNING);
+        let start = Instant::now();
+        let state = state(LandingGearStatus::Up, false);
+
+        // The lever was moved down, but the gear does not follow
+        logic.update(&state, Some(true), start);
+        let indication = logic.update(&state, Some(true), start + Duration::from_secs(10));
+
+        assert!(indication.disagreement);
+        assert_eq!(indication.left, GearLight::Red);
+
+        // Moving the lever back ends the disagreement
+        let indication = logic.update(&state, Some(false), start + Duration::from_secs(11));
+        assert!(!indication.disagreement);
+        assert_eq!(indication.left, GearLight::Off);
+    }
+
+    #[test]
+    fn leg_stuck_in_transit_disagrees() {
+        let mut logic = GearLogic::new(WARNING);
+        let start = Instant::now();
+        let mut state = state(LandingGearStatus::Down, true);
+        state.gear_left_state = LandingGearStatus::from(0.5);
+
+        logic.update(&state, None, start);
+        let indication = logic.update(&state, None, start + Duration::from_secs(10));
+
+        assert!(indication.disagreement);
+        assert_eq!(indication.front, GearLight::Green);
+        assert_eq!(indication.left, GearLight::Red);
+        assert_eq!(indication.right, GearLight::Green);
+    }
+
+    #[test]
+    fn unsafe_warning_when_slow_or_low() {
+        let mut logic = GearLogic::new(WARNING);
+        let now = Instant::now();
+        let mut state = state(LandingGearStatus::Up, false);
+        assert!(!logic.update(&state, None, now).unsafe_warning);
+
+        state.airspeed = 90.0;
+        assert!(logic.update(&state, None, now).unsafe_warning);
+
+        state.airspeed = 150.0;
+        state.altitude_above_ground = 500.0;
+        assert!(logic.update(&state, None, now).unsafe_warning);
+
+        // Gear down and locked is safe
+        let state = AircraftSimState {
+            altitude_above_ground: 500.0,
+            ..self::state(LandingGearStatus::Down, true)
+        };
+        assert!(!logic.update(&state, None, now).unsafe_warning);
+    }
+
+    #[test]
+    fn no_unsafe_warning_on_ground() {
+        let mut logic = GearLogic::new(WARNING);
+        let state = AircraftSimState {
+            on_ground: true,
+            airspeed: 0.0,
+            altitude_above_ground: 0.0,
+            ..state(LandingGearStatus::Up, false)
+        };
+
+        assert!(!logic.update(&state, None, Instant::now()).unsafe_warning);
+    }
+}
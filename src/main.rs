@@ -1,17 +1,37 @@
-use log::{debug, error};
+use log::{debug, error, info};
 use panel::Panel;
+use profile::{Profile, ProfileSelector};
 use sim::{AircraftSimState, SimClientEvent, SimCommunicator};
-use std::sync::mpsc;
-use std::{process, thread};
+use simvar::Subscriber;
+use std::sync::{mpsc, Arc};
+use std::{env, fs, process, thread};
 
 use crate::config::Config;
+use crate::flash::FirmwareImage;
 use crate::panels::airspeedindicator::AirspeedIndicatorPanel;
+use crate::panels::autopilot::AutopilotPanel;
 use crate::panels::eventsim::EventSimPanel;
+use crate::panels::gauge::GaugePanel;
+use crate::panels::radio::RadioPanel;
+use crate::panels::transponder::TransponderPanel;
 
+mod axis;
+mod calibrate;
 mod config;
+mod encoder;
+mod flash;
+mod gear;
+mod needle;
+mod output;
 mod panel;
 mod panels;
+mod profile;
+mod radio;
 mod sim;
+mod simvar;
+
+/// Path of the configuration file.
+const CONFIG_PATH: &str = "config.toml";
 
 #[derive(Debug)]
 pub enum Event {
@@ -19,6 +39,10 @@ pub enum Event {
     SetSimulator(SimClientEvent),
     /// The simulator aircraft state changed.
     SetPanel(AircraftSimState),
+    /// The connection with the simulator was (re-)established.
+    SimConnected,
+    /// An aircraft was loaded, which uses the given profile or the panel configuration.
+    SetProfile(Option<Arc<Profile>>),
 }
 
 fn run(config: Config) {
@@ -26,24 +50,57 @@ fn run(config: Config) {
     let (hw_tx, hw_rx) = mpsc::channel();
 
     let mut panels: Vec<Box<dyn Panel>> = Vec::new();
-    let mut sim_txs = Vec::new();
+    let mut subscribers = Vec::new();
 
     // Initialization of EventSim panel
     if let Some(port) = config.eventsim_port() {
         let (sim_tx, sim_rx) = mpsc::channel();
-        let panel = EventSimPanel::new(port, hw_tx.clone(), sim_rx);
+        let panel = EventSimPanel::new(port, &config, hw_tx.clone(), sim_rx);
+        subscribers.push(Subscriber::new(sim_tx, panel.sim_vars(), &config.epsilons));
         panels.push(Box::new(panel));
-        sim_txs.push(sim_tx);
     };
 
     // Initialization of airspeed indicator
     if let Some(port) = config.airspeedindicator_port() {
         let (sim_tx, sim_rx) = mpsc::channel();
-        let panel = AirspeedIndicatorPanel::new(port, sim_rx);
+        let panel = AirspeedIndicatorPanel::new(port, &config, sim_rx);
+        subscribers.push(Subscriber::new(sim_tx, panel.sim_vars(), &config.epsilons));
+        panels.push(Box::new(panel));
+    };
+
+    // Initialization of autopilot panel
+    if let Some(port) = config.autopilot_port() {
+        let (sim_tx, sim_rx) = mpsc::channel();
+        let panel = AutopilotPanel::new(port, &config, hw_tx.clone(), sim_rx);
+        subscribers.push(Subscriber::new(sim_tx, panel.sim_vars(), &config.epsilons));
         panels.push(Box::new(panel));
-        sim_txs.push(sim_tx);
     };
 
+    // Initialization of radio panel
+    if let Some(port) = config.radio_port() {
+        let (sim_tx, sim_rx) = mpsc::channel();
+        let panel = RadioPanel::new(port, &config, hw_tx.clone(), sim_rx);
+        subscribers.push(Subscriber::new(sim_tx, panel.sim_vars(), &config.epsilons));
+        panels.push(Box::new(panel));
+    };
+
+    // Initialization of transponder panel
+    if let Some(port) = config.transponder_port() {
+        let (sim_tx, sim_rx) = mpsc::channel();
+        let panel = TransponderPanel::new(port, &config, hw_tx.clone(), sim_rx);
+        subscribers.push(Subscriber::new(sim_tx, panel.sim_vars(), &config.epsilons));
+        panels.push(Box::new(panel));
+    };
+
+    // Initialization of stepper gauge panels
+    for name in config.gauge_panels() {
+        let port = config.panel_port(&name).expect("configured panel");
+        let (sim_tx, sim_rx) = mpsc::channel();
+        let panel = GaugePanel::new(&name, port, &config, hw_tx.clone(), sim_rx);
+        subscribers.push(Subscriber::new(sim_tx, panel.sim_vars(), &config.epsilons));
+        panels.push(Box::new(panel));
+    }
+
     // Start threads
     let mut handles = Vec::new();
     for mut panel in panels {
@@ -53,8 +110,9 @@ fn run(config: Config) {
             }
         }));
     }
+    let profiles = ProfileSelector::new(&config);
     handles.push(thread::spawn(move || {
-        SimCommunicator::new(sim_txs, hw_rx).run()
+        SimCommunicator::new(subscribers, hw_rx, profiles).run()
     }));
 
     for handle in handles {
@@ -62,9 +120,51 @@ fn run(config: Config) {
     }
 }
 
+/// Upload a firmware image to the panel with the given name and verify it with a handshake.
+fn flash(config: Config, name: &str, hex_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let port = config
+        .panel_port(name)
+        .ok_or_else(|| format!("No panel named '{name}' in the configuration"))?;
+    let baud_rate = config
+        .panel_bootloader_baud_rate(name)
+        .unwrap_or(flash::DEFAULT_BOOTLOADER_BAUD_RATE);
+    let hex = fs::read_to_string(hex_path)
+        .map_err(|e| format!("Failed to open firmware image '{hex_path}': {e}"))?;
+    let image = FirmwareImage::from_hex(&hex)?;
+
+    // The panel is only used for the handshake, so nothing is connected to its channels
+    let (hw_tx, _) = mpsc::channel();
+    let (_, sim_rx) = mpsc::channel();
+    let mut panel: Box<dyn Panel> = match name {
+        "eventsim" => Box::new(EventSimPanel::new(&port, &config, hw_tx, sim_rx)),
+        "airspeedindicator" => Box::new(AirspeedIndicatorPanel::new(&port, &config, sim_rx)),
+        "autopilot" => Box::new(AutopilotPanel::new(&port, &config, hw_tx, sim_rx)),
+        "radio" => Box::new(RadioPanel::new(&port, &config, hw_tx, sim_rx)),
+        "transponder" => Box::new(TransponderPanel::new(&port, &config, hw_tx, sim_rx)),
+        name if config.gauge_panels().iter().any(|gauges| gauges == name) => {
+            Box::new(GaugePanel::new(name, &port, &config, hw_tx, sim_rx))
+        }
+        _ => return Err(format!("Unknown panel type '{name}'").into()),
+    };
+
+    let capabilities = flash::flash(&port, baud_rate, &image, panel.as_mut())?;
+    info!(
+        "Successfully flashed panel '{name}' with firmware {}",
+        capabilities.firmware
+    );
+    Ok(())
+}
+
+/// Record the travel of a panel axis and store its calibration in the configuration.
+fn calibrate(config: Config, name: &str, axis: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let calibration = calibrate::calibrate(&config, CONFIG_PATH, name, axis)?;
+    info!("Stored calibration of axis '{axis}' of panel '{name}': {calibration:?}");
+    Ok(())
+}
+
 fn main() {
     // Parse the app configuration
-    let config = Config::from_file("config.toml").unwrap_or_else(|e| {
+    let config = Config::from_file(CONFIG_PATH).unwrap_or_else(|e| {
         eprintln!("{e}");
         process::exit(1)
     });
@@ -74,6 +174,30 @@ fn main() {
     let level = config.log_level.as_str();
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level)).init();
 
-    // Run the application
-    run(config);
+    // Run the application or one of its subcommands
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => run(config),
+        ["flash", name, hex_path] => {
+            if let Err(e) = flash(config, name, hex_path) {
+                error!("{e}");
+                process::exit(1)
+            }
+        }
+        ["calibrate", name, axis] => {
+            if let Err(e) = calibrate(config, name, axis) {
+                error!("{e}");
+                process::exit(1)
+            }
+        }
+        _ => {
+            eprintln!("Usage: picard [flash <panel> <hex> | calibrate <panel> <axis>]");
+            process::exit(2)
+        }
+    }
 }
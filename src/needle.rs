@@ -0,0 +1,292 @@
+use std::time::Instant;
+
+use crate::config::{GaugeConfig, GaugeSource};
+use crate::sim::AircraftSimState;
+use crate::simvar::SimVar;
+
+impl GaugeSource {
+    /// SimVar the source is read from.
+    pub fn sim_var(&self) -> SimVar {
+        match self {
+            GaugeSource::Airspeed => SimVar::Airspeed,
+            GaugeSource::Altitude => SimVar::IndicatedAltitude,
+            GaugeSource::VerticalSpeed => SimVar::VerticalSpeed,
+            GaugeSource::Heading => SimVar::Heading,
+            GaugeSource::TurnRate => SimVar::TurnRate,
+            GaugeSource::SlipBall => SimVar::SlipBall,
+            GaugeSource::Pitch => SimVar::Pitch,
+            GaugeSource::Bank => SimVar::Bank,
+        }
+    }
+
+    /// Range of the simulator value after which a circular source starts over, like the heading after 359°.
+    pub fn period(&self) -> Option<f64> {
+        match self {
+            GaugeSource::Heading => Some(360.0),
+            _ => None,
+        }
+    }
+
+    /// Current simulator value of the source.
+    pub fn value(&self, state: &AircraftSimState) -> f64 {
+        match self {
+            GaugeSource::Airspeed => state.airspeed,
+            GaugeSource::Altitude => state.indicated_altitude,
+            GaugeSource::VerticalSpeed => state.vertical_speed,
+            GaugeSource::Heading => state.heading_indicator,
+            GaugeSource::TurnRate => state.turn_rate,
+            GaugeSource::SlipBall => state.turn_coordinator_ball,
+            GaugeSource::Pitch => state.attitude_pitch,
+            GaugeSource::Bank => state.attitude_bank,
+        }
+    }
+}
+
+impl GaugeConfig {
+    /// Content of the gauge for a simulator value, following the calibration table if there is one.
+    ///
+    /// The table has to be sorted by the value, which `Needle::new` takes care of.
+    pub fn content(&self, value: f64) -> f64 {
+        let (Some(first), Some(last)) = (self.table.first(), self.table.last()) else {
+            return value * self.scale + self.offset;
+        };
+        if value <= first[0] {
+            return first[1];
+        }
+        if value >= last[0] {
+            return last[1];
+        }
+
+        // Interpolate between the points the value lies between
+        self.table
+            .windows(2)
+            .find(|points| value <= points[1][0])
+            .map(|points| {
+                let [from, to] = [points[0], points[1]];
+                if to[0] == from[0] {
+                    return to[1];
+                }
+                from[1] + (value - from[0]) / (to[0] - from[0]) * (to[1] - from[1])
+            })
+            .unwrap_or(last[1])
+    }
+}
+
+/// Needle of a stepper gauge that follows the simulator value smoothly.
+#[derive(Debug)]
+pub struct Needle {
+    pub config: GaugeConfig,
+    /// Unrounded content the needle was last moved to.
+    position: Option<f64>,
+    last_update: Option<Instant>,
+}
+
+impl Needle {
+    pub fn new(mut config: GaugeConfig) -> Self {
+        // The calibration table may be written in any order
+        config.table.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        Self {
+            config,
+            position: None,
+            last_update: None,
+        }
+    }
+
+    /// Move the needle towards the content for the simulator value and return the rounded content to send.
+    pub fn update(&mut self, state: &AircraftSimState, now: Instant) -> i32 {
+        let target = self.config.content(self.config.source.value(state));
+        let elapsed = self
+            .last_update
+            .map(|last| now.duration_since(last).as_secs_f64())
+            .unwrap_or(0.0);
+        self.last_update = Some(now);
+
+        let position = match self.position {
+            // The needle jumps to the first value instead of sweeping across the dial
+            None => target,
+            Some(position) => {
+                let mut step = target - position;
+                // Circular gauges turn the short way round, e.g. from 359° to 0°
+                if let Some((_, revolution)) = self.revolution() {
+                    step -= revolution * (step / revolution).round();
+                }
+                if self.config.smoothing > 0.0 {
+                    step *= 1.0 - (-elapsed / self.config.smoothing).exp();
+                }
+                if let Some(max_rate) = self.config.max_rate {
+                    step = step.clamp(-max_rate * elapsed, max_rate * elapsed);
+                }
+                position + step
+            }
+        };
+        let position = match self.revolution() {
+            Some((start, revolution)) => {
+                start + ((position - start) / revolution).rem_euclid(1.0) * revolution
+            }
+            None => position,
+        };
+        self.position = Some(position);
+        position.round() as i32
+    }
+
+    /// Content of the start of the dial and the content of one revolution, if the source is circular.
+    fn revolution(&self) -> Option<(f64, f64)> {
+        let period = self.config.source.period()?;
+        let start = self.config.content(0.0);
+        let revolution = self.config.content(period) - start;
+        (revolution != 0.0).then_some((start, revolution))
+    }
+
+    /// Let the needle jump to the next value, e.g. after the simulator loaded a different aircraft.
+    pub fn reset(&mut self) {
+        self.position = None;
+        self.last_update = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn gauge(source: GaugeSource, table: &[[f64; 2]]) -> GaugeConfig {
+        GaugeConfig {
+            table: table.to_vec(),
+            ..GaugeConfig::unscaled(source)
+        }
+    }
+
+    fn airspeed(airspeed: f64) -> AircraftSimState {
+        AircraftSimState {
+            airspeed,
+            ..Default::default()
+        }
+    }
+
+    fn heading(heading_indicator: f64) -> AircraftSimState {
+        AircraftSimState {
+            heading_indicator,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn content_interpolates_between_points() {
+        let config = gauge(
+            GaugeSource::Airspeed,
+            &[[0.0, 0.0], [100.0, 1000.0], [200.0, 1500.0]],
+        );
+
+        assert_eq!(config.content(50.0), 500.0);
+        assert_eq!(config.content(100.0), 1000.0);
+        assert_eq!(config.content(150.0), 1250.0);
+    }
+
+    #[test]
+    fn content_clamps_to_first_and_last_point() {
+        let config = gauge(GaugeSource::Airspeed, &[[40.0, 100.0], [200.0, 1500.0]]);
+
+        assert_eq!(config.content(0.0), 100.0);
+        assert_eq!(config.content(250.0), 1500.0);
+    }
+
+    #[test]
+    fn content_without_table_scales_value() {
+        let config = GaugeConfig {
+            scale: 2.0,
+            offset: 10.0,
+            ..GaugeConfig::unscaled(GaugeSource::Airspeed)
+        };
+
+        assert_eq!(config.content(5.0), 20.0);
+    }
+
+    #[test]
+    fn content_of_single_point_table_is_constant() {
+        let config = gauge(GaugeSource::Airspeed, &[[50.0, 300.0]]);
+
+        assert_eq!(config.content(0.0), 300.0);
+        assert_eq!(config.content(100.0), 300.0);
+    }
+
+    #[test]
+    fn needle_sorts_table() {
+        let needle = Needle::new(gauge(
+            GaugeSource::Airspeed,
+            &[[200.0, 1500.0], [0.0, 0.0], [100.0, 1000.0]],
+        ));
+
+        assert_eq!(needle.config.content(50.0), 500.0);
+        assert_eq!(needle.config.content(150.0), 1250.0);
+    }
+
+    #[test]
+    fn needle_rounds_content() {
+        let mut needle = Needle::new(GaugeConfig::unscaled(GaugeSource::Airspeed));
+        let now = Instant::now();
+
+        assert_eq!(needle.update(&airspeed(12.4), now), 12);
+        needle.reset();
+        assert_eq!(needle.update(&airspeed(12.6), now), 13);
+    }
+
+    #[test]
+    fn needle_follows_with_smoothing() {
+        let mut needle = Needle::new(GaugeConfig {
+            smoothing: 1.0,
+            ..GaugeConfig::unscaled(GaugeSource::Airspeed)
+        });
+        let start = Instant::now();
+
+        // The first value is shown right away
+        assert_eq!(needle.update(&airspeed(0.0), start), 0);
+        // After one time constant the needle covered 1 - 1/e of the way
+        let position = needle.update(&airspeed(100.0), start + Duration::from_secs(1));
+        assert_eq!(position, 63);
+    }
+
+    #[test]
+    fn needle_moves_at_max_rate() {
+        let mut needle = Needle::new(GaugeConfig {
+            max_rate: Some(10.0),
+            ..GaugeConfig::unscaled(GaugeSource::Airspeed)
+        });
+        let start = Instant::now();
+
+        needle.update(&airspeed(0.0), start);
+        assert_eq!(
+            needle.update(&airspeed(100.0), start + Duration::from_secs(2)),
+            20
+        );
+        assert_eq!(
+            needle.update(&airspeed(0.0), start + Duration::from_secs(3)),
+            10
+        );
+    }
+
+    #[test]
+    fn heading_needle_turns_short_way_round() {
+        let mut needle = Needle::new(GaugeConfig {
+            max_rate: Some(10.0),
+            ..GaugeConfig::unscaled(GaugeSource::Heading)
+        });
+        let start = Instant::now();
+
+        needle.update(&heading(350.0), start);
+        // From 350° to 10° the needle turns right across north instead of back through 180°
+        assert_eq!(
+            needle.update(&heading(10.0), start + Duration::from_secs(1)),
+            0
+        );
+        assert_eq!(
+            needle.update(&heading(10.0), start + Duration::from_millis(1500)),
+            5
+        );
+        // And back to the left the same way
+        assert_eq!(
+            needle.update(&heading(350.0), start + Duration::from_millis(2500)),
+            355
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use log::{debug, info};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, OutputLimits};
+
+/// Number of messages that may wait in an output queue before the oldest ones are dropped.
+const QUEUE_CAPACITY: usize = 64;
+
+/// Time of transfer the output queue may write at once after the serial link was idle.
+const QUEUE_BURST: Duration = Duration::from_millis(50);
+
+/// Interval in which the counters of the output queues are logged.
+const METRICS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Value that was last written to an output.
+#[derive(Debug)]
+struct Written {
+    value: String,
+    at: Instant,
+}
+
+/// Decides which values are written to the outputs of a panel according to their limits.
+///
+/// The panels evaluate their outputs continuously, so a value that is held back by the rate limit is written by a
+/// later evaluation.
+#[derive(Debug)]
+pub struct OutputFilter {
+    limits: OutputLimits,
+    output_limits: HashMap<String, OutputLimits>,
+    written: HashMap<String, Written>,
+}
+
+impl OutputFilter {
+    /// Filter with the limits of the panel with the given name, using `defaults` for the limits that are not configured.
+    pub fn from_config(config: &Config, name: &str, defaults: OutputLimits) -> Self {
+        Self {
+            limits: config.panel_limits(name).or(defaults),
+            output_limits: config.panel_output_limits(name),
+            written: HashMap::new(),
+        }
+    }
+
+    /// Whether the value should be written to the output now, in which case it is recorded as written.
+    ///
+    /// The deadband applies to values that are numbers, all other values are written whenever they change.
+    pub fn update(&mut self, channel: &str, value: &str, now: Instant) -> bool {
+        let limits = self
+            .output_limits
+            .get(channel)
+            .map(|limits| limits.or(self.limits))
+            .unwrap_or(self.limits);
+
+        if let Some(written) = self.written.get(channel) {
+            let elapsed = now.duration_since(written.at);
+            let stale = limits
+                .max_staleness_ms
+                .is_some_and(|staleness| elapsed >= Duration::from_millis(staleness));
+            let changed = match (written.value.parse::<f64>(), value.parse::<f64>()) {
+                (Ok(old), Ok(new)) => (new - old).abs() > limits.deadband.unwrap_or(0.0),
+                _ => written.value != value,
+            };
+            let due = limits
+                .max_rate
+                .is_none_or(|rate| elapsed.as_secs_f64() >= 1.0 / rate);
+            if !(stale || changed && due) {
+                return false;
+            }
+        }
+
+        self.written.insert(
+            channel.into(),
+            Written {
+                value: value.into(),
+                at: now,
+            },
+        );
+        true
+    }
+
+    /// Forget the written values, so that every output is written again.
+    pub fn clear(&mut self) {
+        self.written.clear();
+    }
+}
+
+/// Counters of the messages that went through an output queue.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct OutputMetrics {
+    /// Messages written to the serial port.
+    written: u64,
+    /// Messages that replaced a pending message of the same channel.
+    merged: u64,
+    /// Messages dropped because the queue was full.
+    dropped: u64,
+}
+
+/// Message waiting in an output queue.
+#[derive(Debug)]
+struct Pending {
+    channel: String,
+    message: Vec<u8>,
+}
+
+/// Buffers the messages to a panel, so that a slow or blocked serial port does not stall the panel loop.
+///
+/// A message replaces the pending message of the same channel, as only the latest value matters. Messages are written
+/// no faster than the serial link transfers them, using a budget of bytes per second derived from the baud rate.
+#[derive(Debug)]
+pub struct OutputQueue {
+    name: String,
+    pending: VecDeque<Pending>,
+    /// Bytes of the first pending message that were already written.
+    offset: usize,
+    bytes_per_sec: f64,
+    /// Bytes that may be written now, negative after a message exceeded the budget.
+    credit: f64,
+    refilled: Instant,
+    metrics: OutputMetrics,
+    reported: (Instant, OutputMetrics),
+}
+
+impl OutputQueue {
+    /// Queue for the panel with the given name that is connected with the given baud rate.
+    pub fn new(name: &str, baud_rate: u32) -> Self {
+        let now = Instant::now();
+        Self {
+            name: name.into(),
+            pending: VecDeque::new(),
+            offset: 0,
+            // Every byte is sent with a start and a stop bit
+            bytes_per_sec: f64::from(baud_rate) / 10.0,
+            credit: 0.0,
+            refilled: now,
+            metrics: OutputMetrics::default(),
+            reported: (now, OutputMetrics::default()),
+        }
+    }
+
+    /// Queue a message for the channel, replacing the message that is still pending for it.
+    pub fn push(&mut self, channel: &str, message: impl Into<Vec<u8>>) {
+        let message = message.into();
+        // A partially written message must be completed as it is
+        let skip = usize::from(self.offset > 0);
+        if let Some(pending) = self
+            .pending
+            .iter_mut()
+            .skip(skip)
+            .find(|pending| pending.channel == channel)
+        {
+            pending.message = message;
+            self.metrics.merged += 1;
+            return;
+        }
+
+        if self.pending.len() >= QUEUE_CAPACITY {
+            self.pending.remove(skip);
+            self.metrics.dropped += 1;
+        }
+        self.pending.push_back(Pending {
+            channel: channel.into(),
+            message,
+        });
+    }
+
+    /// Write the pending messages the budget allows without blocking on a full serial port.
+    pub fn flush(&mut self, writer: &mut impl Write) -> io::Result<()> {
+        self.flush_at(writer, Instant::now())
+    }
+
+    /// Write the pending messages the budget allows at the given time.
+    fn flush_at(&mut self, writer: &mut impl Write, now: Instant) -> io::Result<()> {
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.credit = (self.credit + elapsed * self.bytes_per_sec)
+            .min(self.bytes_per_sec * QUEUE_BURST.as_secs_f64());
+        self.refilled = now;
+
+        while self.credit > 0.0 {
+            let Some(pending) = self.pending.front() else {
+                break;
+            };
+            let written = match writer.write(&pending.message[self.offset..]) {
+                Ok(0) => break,
+                Ok(written) => written,
+                // The device does not take any more data right now, try again with the next flush
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    break
+                }
+                Err(e) => return Err(e),
+            };
+            self.credit -= written as f64;
+            self.offset += written;
+            if self.offset == pending.message.len() {
+                self.pending.pop_front();
+                self.offset = 0;
+                self.metrics.written += 1;
+            }
+        }
+
+        self.report(now);
+        Ok(())
+    }
+
+    /// Log the counters periodically, more prominently when messages had to be dropped.
+    fn report(&mut self, now: Instant) {
+        let (at, reported) = self.reported;
+        if now.duration_since(at) < METRICS_INTERVAL || self.metrics == reported {
+            return;
+        }
+        let OutputMetrics {
+            written,
+            merged,
+            dropped,
+        } = self.metrics;
+        if dropped > reported.dropped {
+            info!(
+                "{} output queue: {written} written, {merged} merged, {dropped} dropped",
+                self.name
+            );
+        } else {
+            debug!(
+                "{} output queue: {written} written, {merged} merged, {dropped} dropped",
+                self.name
+            );
+        }
+        self.reported = (now, self.metrics);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(limits: OutputLimits, output_limits: &[(&str, OutputLimits)]) -> OutputFilter {
+        OutputFilter {
+            limits,
+            output_limits: output_limits
+                .iter()
+                .map(|(channel, limits)| (channel.to_string(), *limits))
+                .collect(),
+            written: HashMap::new(),
+        }
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn filter_suppresses_changes_within_deadband() {
+        let mut filter = filter(
+            OutputLimits {
+                deadband: Some(1.0),
+                ..Default::default()
+            },
+            &[],
+        );
+        let now = Instant::now();
+
+        assert!(filter.update("ALT", "100", now));
+        assert!(!filter.update("ALT", "100.5", now + ms(100)));
+        assert!(filter.update("ALT", "101.5", now + ms(200)));
+        // Values that are not numbers are written whenever they change
+        assert!(filter.update("MODE", "ALT", now));
+        assert!(!filter.update("MODE", "ALT", now + ms(100)));
+        assert!(filter.update("MODE", "VS", now + ms(200)));
+    }
+
+    #[test]
+    fn filter_suppresses_updates_above_max_rate() {
+        let mut filter = filter(
+            OutputLimits {
+                max_rate: Some(10.0),
+                ..Default::default()
+            },
+            &[],
+        );
+        let now = Instant::now();
+
+        assert!(filter.update("HDG", "1", now));
+        assert!(!filter.update("HDG", "2", now + ms(50)));
+        assert!(filter.update("HDG", "2", now + ms(100)));
+    }
+
+    #[test]
+    fn filter_resends_stale_values() {
+        let mut filter = filter(
+            OutputLimits {
+                deadband: Some(5.0),
+                max_staleness_ms: Some(1000),
+                ..Default::default()
+            },
+            &[],
+        );
+        let now = Instant::now();
+
+        assert!(filter.update("ALT", "100", now));
+        assert!(!filter.update("ALT", "101", now + ms(500)));
+        assert!(filter.update("ALT", "101", now + ms(1000)));
+        assert!(!filter.update("ALT", "101", now + ms(1500)));
+    }
+
+    #[test]
+    fn output_limits_take_precedence_over_panel_limits() {
+        let panel = OutputLimits {
+            max_rate: Some(1.0),
+            deadband: Some(5.0),
+            max_staleness_ms: None,
+        };
+        let flaps = OutputLimits {
+            deadband: Some(0.5),
+            ..Default::default()
+        };
+        let mut filter = filter(panel, &[("FLAPS", flaps)]);
+        let now = Instant::now();
+
+        assert!(filter.update("FLAPS", "1", now));
+        assert!(filter.update("ALT", "1", now));
+        assert!(filter.update("FLAPS", "2", now + ms(1000)));
+        assert!(!filter.update("ALT", "2", now + ms(1000)));
+        // The output inherits the rate limit of the panel
+        assert!(!filter.update("FLAPS", "3", now + ms(1500)));
+        assert!(filter.update("FLAPS", "3", now + ms(2000)));
+    }
+
+    /// Serial port that takes at most the given number of bytes.
+    struct Port {
+        written: Vec<u8>,
+        capacity: usize,
+    }
+
+    impl Write for Port {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let length = buf.len().min(self.capacity - self.written.len());
+            if length == 0 {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            self.written.extend_from_slice(&buf[..length]);
+            Ok(length)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn port() -> Port {
+        Port {
+            written: Vec::new(),
+            capacity: usize::MAX,
+        }
+    }
+
+    #[test]
+    fn queue_coalesces_messages_of_a_channel() {
+        let mut queue = OutputQueue::new("Test", 115200);
+        let mut port = port();
+        queue.push("ALT", "ALT:100\n");
+        queue.push("HDG", "HDG:90\n");
+        queue.push("ALT", "ALT:200\n");
+
+        queue.flush_at(&mut port, queue.refilled + ms(10)).unwrap();
+
+        assert_eq!(port.written, b"ALT:200\nHDG:90\n");
+        assert_eq!(queue.metrics.merged, 1);
+        assert_eq!(queue.metrics.written, 2);
+    }
+
+    #[test]
+    fn queue_keeps_partially_written_message() {
+        let mut queue = OutputQueue::new("Test", 115200);
+        let mut port = Port {
+            written: Vec::new(),
+            capacity: 4,
+        };
+        queue.push("ALT", "ALT:100\n");
+        queue.flush_at(&mut port, queue.refilled + ms(10)).unwrap();
+
+        // The rest of the message is sent as it is, the new value follows it
+        queue.push("ALT", "ALT:200\n");
+        port.capacity = usize::MAX;
+        queue.flush_at(&mut port, queue.refilled + ms(10)).unwrap();
+
+        assert_eq!(port.written, b"ALT:100\nALT:200\n");
+    }
+
+    #[test]
+    fn queue_carries_messages_beyond_budget_over() {
+        // 960 bytes per second, so 10 ms allow 9.6 bytes
+        let mut queue = OutputQueue::new("Test", 9600);
+        let mut port = port();
+        for channel in ["A", "B", "C", "D", "E"] {
+            queue.push(channel, format!("{channel}:1\n"));
+        }
+        let start = queue.refilled;
+
+        // The message that exceeds the budget is still written and the following ones wait
+        queue.flush_at(&mut port, start + ms(10)).unwrap();
+        assert_eq!(port.written, b"A:1\nB:1\nC:1\n");
+        assert_eq!(queue.pending.len(), 2);
+
+        queue.flush_at(&mut port, start + ms(20)).unwrap();
+        assert_eq!(port.written, b"A:1\nB:1\nC:1\nD:1\nE:1\n");
+        assert!(queue.pending.is_empty());
+    }
+}
@@ -1,7 +1,135 @@
 use core::fmt;
+use log::debug;
+use serialport::SerialPort;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::{ResetConfig, ResetStrategy};
+use crate::simvar::SimVar;
 
 pub trait Panel: Send {
     fn run(&mut self) -> Result<(), PanelError>;
+
+    /// SimVars the panel reads from the aircraft state, which is only sent to the panel when one of them changes.
+    fn sim_vars(&self) -> Vec<SimVar>;
+
+    /// Connect to the panel, complete the handshake and report the capabilities of its firmware.
+    fn probe(&mut self) -> Result<Capabilities, PanelError>;
+}
+
+/// Open the serial port of a panel and reset the device according to the configured strategy.
+///
+/// The protocols detect themselves when the sketch is running within the `ready_timeout` of the reset: frame panels
+/// announce themselves with a banner, while line panels stay silent until they answer a `SYN`.
+pub fn open_serial(
+    port: &str,
+    baud_rate: u32,
+    reset: ResetConfig,
+) -> Result<Box<dyn SerialPort>, PanelError> {
+    debug!("Attempting to connect to panel on serial port {port}");
+    let mut serial = serialport::new(port, baud_rate)
+        .timeout(Duration::from_millis(10))
+        .open()
+        .map_err(|e| PanelError::SerialOpen(port.into(), e))?;
+
+    if reset.strategy == ResetStrategy::None {
+        return Ok(serial);
+    }
+
+    // Reset device
+    serial.write_data_terminal_ready(false)?;
+    thread::sleep(Duration::from_millis(50));
+    serial.write_data_terminal_ready(true)?;
+    serial.clear(serialport::ClearBuffer::All)?;
+    Ok(serial)
+}
+
+/// Firmware version reported by a panel in the form `<major>.<minor>.<patch>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl FirmwareVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for FirmwareVersion {
+    type Err = PanelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(3, '.').map(|part| part.parse::<u32>());
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch))) => {
+                Ok(Self::new(major, minor, patch))
+            }
+            _ => Err(PanelError::WrongDevice),
+        }
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Firmware version and channels a panel reported during the handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub firmware: FirmwareVersion,
+    pub channels: HashSet<String>,
+}
+
+impl Capabilities {
+    pub fn new<'a>(firmware: FirmwareVersion, channels: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            firmware,
+            channels: channels
+                .into_iter()
+                .map(str::trim)
+                .filter(|channel| !channel.is_empty())
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    /// Whether the panel reported support for the given channel.
+    pub fn supports(&self, channel: &str) -> bool {
+        self.channels.contains(channel)
+    }
+
+    /// Verify that the firmware is within the supported range and provides all required channels.
+    pub fn check(
+        &self,
+        firmware: &Range<FirmwareVersion>,
+        required_channels: &[&str],
+    ) -> Result<(), PanelError> {
+        if !firmware.contains(&self.firmware) {
+            return Err(PanelError::IncompatibleFirmware(
+                self.firmware,
+                firmware.clone(),
+            ));
+        }
+        match required_channels
+            .iter()
+            .find(|channel| !self.supports(channel))
+        {
+            Some(channel) => Err(PanelError::MissingChannel(channel.to_string())),
+            None => Ok(()),
+        }
+    }
 }
 
 /// Errors related to the panel.
@@ -13,6 +141,12 @@ pub enum PanelError {
     Disconnect,
     /// We are not connected to the expected device
     WrongDevice,
+    /// The panel speaks a protocol version we do not support
+    UnsupportedProtocol(u32),
+    /// The panel firmware is outside of the supported version range
+    IncompatibleFirmware(FirmwareVersion, Range<FirmwareVersion>),
+    /// The panel firmware does not provide a channel we require
+    MissingChannel(String),
     /// Error that relates to the serial port
     Serial(serialport::Error),
     /// I/O error that wraps the standard error type
@@ -32,6 +166,17 @@ impl fmt::Display for PanelError {
             PanelError::WrongDevice => {
                 write!(f, "Connected device is likely not the expected panel")
             }
+            PanelError::UnsupportedProtocol(version) => {
+                write!(f, "Panel uses unsupported protocol version {version}")
+            }
+            PanelError::IncompatibleFirmware(version, supported) => write!(
+                f,
+                "Panel firmware {version} is incompatible, expected at least {} and below {}",
+                supported.start, supported.end
+            ),
+            PanelError::MissingChannel(channel) => {
+                write!(f, "Panel firmware does not support channel '{channel}'")
+            }
             PanelError::Serial(e) => write!(f, "Serial communication error: {}", e),
             PanelError::Io(e) => write!(f, "Panel I/O error: {}", e),
         }
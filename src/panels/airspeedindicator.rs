@@ -1,83 +1,122 @@
-use log::{debug, info};
-use std::io::{BufRead, BufReader};
+use log::debug;
 use std::sync::mpsc;
-use std::thread;
-use std::time::Duration;
+use std::time::Instant;
 
-use crate::panel::{Panel, PanelError};
+use crate::config::{Config, GaugeConfig, GaugeSource, ResetConfig};
+use crate::needle::Needle;
+use crate::output::OutputFilter;
+use crate::panel::{Capabilities, FirmwareVersion, Panel, PanelError};
+use crate::panels::frame::{Frame, FrameConnection, FrameProtocol, DEFAULT_LIMITS};
+use crate::profile::Profile;
+use crate::sim::AircraftSimState;
+use crate::simvar::SimVar;
 use crate::Event;
 
-/// The baud rate of the Arduino used for the serial connection.
-const BAUD_RATE: u32 = 38400;
+/// Serial protocol of the airspeed indicator.
+const PROTOCOL: FrameProtocol = FrameProtocol {
+    device: "Airspeed-Indicator",
+    // The baud rate of the Arduino used for the serial connection
+    baud_rate: 38400,
+    firmware_versions: FirmwareVersion::new(1, 0, 0)..FirmwareVersion::new(2, 0, 0),
+    required_channels: &["Airspeed-Indicator"],
+};
 
 /// Represents the AirspeedIndicator Main Panel and holds all state and information.
 #[derive(Debug)]
 pub struct AirspeedIndicatorPanel {
     port: String,
+    reset: ResetConfig,
+    /// Gauge of the panel configuration, which applies unless the aircraft profile replaces it.
+    configured: GaugeConfig,
+    needle: Needle,
+    /// Sources of the configured gauge and of the gauges of the aircraft profiles.
+    sources: Vec<GaugeSource>,
     sim_rx: mpsc::Receiver<Event>,
+    aircraft_sim_state: Option<AircraftSimState>,
+    outputs: OutputFilter,
 }
 
 impl Panel for AirspeedIndicatorPanel {
     /// Connect to the panel and run an event loop.
     fn run(&mut self) -> Result<(), PanelError> {
-        debug!(
-            "Attempting to connect to panel on serial port {}",
-            self.port
-        );
-        let mut serial = serialport::new(&self.port, BAUD_RATE)
-            .timeout(Duration::from_millis(10))
-            .open()
-            .map_err(|e| PanelError::SerialOpen(self.port.clone(), e))?;
-
-        // Reset device
-        serial.write_data_terminal_ready(true)?;
-        serial.clear(serialport::ClearBuffer::All)?;
-        // Wait for device to finish resetting
-        thread::sleep(Duration::from_millis(2000));
-
-        // Setup reader for initial device message
-        let mut reader = BufReader::with_capacity(1, serial.try_clone()?);
-        let mut buf = vec![];
-
-        // Verify that we are connected to the correct arduino
-        reader.read_until(b';', &mut buf)?;
-        let initial_msg = String::from_utf8_lossy(&buf);
-        debug!("Initial airspeed indicator message: '{initial_msg}'");
-        if initial_msg == "Name<Airspeed-Indicator>;" {
-            info!(
-                "Connection with airspeed indicator panel established via {}",
-                self.port
-            );
-        } else {
-            return Err(PanelError::WrongDevice);
-        }
+        let mut conn = FrameConnection::open(&self.port, self.reset, &PROTOCOL)?;
 
         loop {
             // Receive control messages
             match self.sim_rx.try_recv() {
                 Ok(Event::SetPanel(state)) => {
-                    writeln!(
-                        serial,
-                        "Type<I-A>::Target<Airspeed-Indicator>::Content<{}>::Origin<Interface>;",
-                        state.airspeed as i32
-                    )?;
+                    self.aircraft_sim_state = Some(state);
+                }
+                Ok(Event::SimConnected) => {
+                    self.aircraft_sim_state = None;
+                    self.needle.reset();
+                    self.outputs.clear();
                 }
+                Ok(Event::SetProfile(profile)) => self.apply_profile(profile.as_deref()),
                 Err(mpsc::TryRecvError::Disconnected) => {
                     // The simconnect thread cannot exit, we exit always first
                     unreachable!();
                 }
                 _ => {}
             }
+
+            // Write the queued frames, waiting for the serial timeout keeps moving a smoothed needle
+            if let Some(frame) = conn.poll()? {
+                debug!("Ignoring frame from the airspeed indicator: {frame:?}");
+            }
+
+            let Some(state) = self.aircraft_sim_state.as_ref() else {
+                continue;
+            };
+            let now = Instant::now();
+            let content = self.needle.update(state, now).to_string();
+            if self.outputs.update(PROTOCOL.device, &content, now) {
+                conn.send(&Frame::to_instrument(PROTOCOL.device, content));
+            }
         }
     }
+
+    fn probe(&mut self) -> Result<Capabilities, PanelError> {
+        let conn = FrameConnection::open(&self.port, self.reset, &PROTOCOL)?;
+        Ok(conn.capabilities)
+    }
+
+    fn sim_vars(&self) -> Vec<SimVar> {
+        self.sources.iter().map(GaugeSource::sim_var).collect()
+    }
 }
 
 impl AirspeedIndicatorPanel {
     /// Create a new panel instance.
-    pub fn new(port: impl AsRef<str>, sim_rx: mpsc::Receiver<Event>) -> Self {
+    pub fn new(port: impl AsRef<str>, config: &Config, sim_rx: mpsc::Receiver<Event>) -> Self {
+        // Without a calibration the firmware is sent the airspeed in knots
+        let gauge = config
+            .panel_gauges("airspeedindicator")
+            .remove(PROTOCOL.device)
+            .unwrap_or_else(|| GaugeConfig::unscaled(GaugeSource::Airspeed));
+        let mut sources = config.panel_gauge_sources("airspeedindicator");
+        if !sources.contains(&gauge.source) {
+            sources.push(gauge.source);
+        }
         Self {
+            reset: config.panel_reset("airspeedindicator"),
+            sources,
+            needle: Needle::new(gauge.clone()),
+            configured: gauge,
             sim_rx,
+            aircraft_sim_state: None,
+            outputs: OutputFilter::from_config(config, "airspeedindicator", DEFAULT_LIMITS),
             port: port.as_ref().into(),
         }
     }
+
+    /// Calibrate the needle as the aircraft profile demands, or as configured without a profile.
+    fn apply_profile(&mut self, profile: Option<&Profile>) {
+        let config = profile
+            .and_then(|profile| profile.gauge("airspeedindicator", PROTOCOL.device))
+            .unwrap_or(&self.configured);
+        if self.needle.config != *config {
+            self.needle = Needle::new(config.clone());
+        }
+    }
 }
@@ -0,0 +1,251 @@
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use crate::config::{Config, OutputLimits, ResetConfig};
+use crate::encoder::Encoder;
+use crate::output::OutputFilter;
+use crate::panel::{Capabilities, FirmwareVersion, Panel, PanelError};
+use crate::panels::line::{LineConnection, LineProtocol};
+use crate::sim::{AircraftSimState, AutopilotState, SimClientEvent};
+use crate::simvar::SimVar;
+use crate::Event;
+
+/// Serial protocol of the autopilot panel.
+const PROTOCOL: LineProtocol = LineProtocol {
+    name: "autopilot",
+    baud_rate: 115200,
+    firmware_versions: FirmwareVersion::new(1, 0, 0)..FirmwareVersion::new(2, 0, 0),
+    required_channels: &["AP_MASTER", "AP_MASTER_LED", "HDG_DISPLAY", "ALT_DISPLAY"],
+    // The panel was released after the capability discovery, so there is no legacy firmware
+    legacy_channels: &[],
+};
+
+/// Mode button of the panel with the annunciator next to it.
+struct Mode {
+    button: &'static str,
+    led: &'static str,
+    event: SimClientEvent,
+    sim_state: fn(&AutopilotState) -> bool,
+}
+
+/// Mode buttons, which toggle the autopilot mode when pressed.
+const MODES: &[Mode] = &[
+    Mode {
+        button: "AP_MASTER",
+        led: "AP_MASTER_LED",
+        event: SimClientEvent::AutopilotMaster,
+        sim_state: |autopilot| autopilot.master,
+    },
+    Mode {
+        button: "HDG",
+        led: "HDG_LED",
+        event: SimClientEvent::AutopilotHeadingHold,
+        sim_state: |autopilot| autopilot.heading_lock,
+    },
+    Mode {
+        button: "ALT",
+        led: "ALT_LED",
+        event: SimClientEvent::AutopilotAltitudeHold,
+        sim_state: |autopilot| autopilot.altitude_lock,
+    },
+    Mode {
+        button: "VS",
+        led: "VS_LED",
+        event: SimClientEvent::AutopilotVerticalSpeedHold,
+        sim_state: |autopilot| autopilot.vertical_hold,
+    },
+    Mode {
+        button: "SPD",
+        led: "SPD_LED",
+        event: SimClientEvent::AutopilotAirspeedHold,
+        sim_state: |autopilot| autopilot.airspeed_hold,
+    },
+    Mode {
+        button: "NAV",
+        led: "NAV_LED",
+        event: SimClientEvent::AutopilotNavHold,
+        sim_state: |autopilot| autopilot.nav1_lock,
+    },
+    Mode {
+        button: "APR",
+        led: "APR_LED",
+        event: SimClientEvent::AutopilotApproachHold,
+        sim_state: |autopilot| autopilot.approach_hold,
+    },
+];
+
+/// Seven-segment display of the panel showing an autopilot target.
+struct Display {
+    channel: &'static str,
+    content: fn(&AircraftSimState) -> String,
+}
+
+/// Displays of the autopilot targets, formatted with the number of digits of the display.
+const DISPLAYS: &[Display] = &[
+    Display {
+        channel: "HDG_DISPLAY",
+        content: |state| format!("{:03}", (state.heading_bug.round() as i32).rem_euclid(360)),
+    },
+    Display {
+        channel: "ALT_DISPLAY",
+        content: |state| format!("{:05}", state.autopilot.altitude.round() as i32),
+    },
+    Display {
+        channel: "VS_DISPLAY",
+        content: |state| format!("{:+05}", state.autopilot.vertical_speed.round() as i32),
+    },
+    Display {
+        channel: "SPD_DISPLAY",
+        content: |state| format!("{:03}", state.autopilot.airspeed.round() as i32),
+    },
+];
+
+/// Represents the autopilot panel with its mode buttons, annunciators, displays and encoders.
+#[derive(Debug)]
+pub struct AutopilotPanel {
+    port: String,
+    reset: ResetConfig,
+    encoders: HashMap<String, Encoder>,
+    hw_tx: mpsc::Sender<Event>,
+    sim_rx: mpsc::Receiver<Event>,
+    aircraft_sim_state: Option<AircraftSimState>,
+    outputs: OutputFilter,
+}
+
+impl Panel for AutopilotPanel {
+    /// Connect to the panel and run an event loop.
+    fn run(&mut self) -> Result<(), PanelError> {
+        let mut conn = LineConnection::open(&self.port, self.reset, &PROTOCOL)?;
+
+        loop {
+            // Receive control messages
+            match self.sim_rx.try_recv() {
+                Ok(Event::SetPanel(state)) => {
+                    self.aircraft_sim_state = Some(state);
+                }
+                Ok(Event::SimConnected) => {
+                    // Write all outputs again once the simulator sends its state
+                    self.aircraft_sim_state = None;
+                    self.outputs.clear();
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    // The simconnect thread cannot exit, we exit always first
+                    unreachable!();
+                }
+                _ => {}
+            }
+
+            // Read messages from serial port
+            if let Some(msg) = conn.poll()? {
+                match msg.as_str() {
+                    msg if msg.starts_with("ENC:") => self.handle_encoder(&msg[4..]),
+                    cmd => self.handle_serial_command(cmd, &conn.capabilities),
+                }
+            }
+
+            self.send_state(&mut conn);
+        }
+    }
+
+    fn probe(&mut self) -> Result<Capabilities, PanelError> {
+        let conn = LineConnection::open(&self.port, self.reset, &PROTOCOL)?;
+        Ok(conn.capabilities)
+    }
+
+    fn sim_vars(&self) -> Vec<SimVar> {
+        let mut sim_vars = vec![SimVar::Autopilot, SimVar::HeadingBug];
+        sim_vars.extend(self.encoders.values().filter_map(Encoder::sim_var));
+        sim_vars
+    }
+}
+
+impl AutopilotPanel {
+    /// Create a new panel instance.
+    pub fn new(
+        port: impl AsRef<str>,
+        config: &Config,
+        hw_tx: mpsc::Sender<Event>,
+        sim_rx: mpsc::Receiver<Event>,
+    ) -> Self {
+        Self {
+            port: port.as_ref().into(),
+            reset: config.panel_reset("autopilot"),
+            encoders: config
+                .panel_encoders("autopilot")
+                .into_iter()
+                .map(|(name, encoder)| (name, Encoder::new(encoder)))
+                .collect(),
+            hw_tx,
+            sim_rx,
+            aircraft_sim_state: None,
+            outputs: OutputFilter::from_config(config, "autopilot", OutputLimits::default()),
+        }
+    }
+
+    /// Write the annunciators and displays that are due according to the output limits.
+    fn send_state(&mut self, conn: &mut LineConnection) {
+        let Some(state) = self.aircraft_sim_state.as_ref() else {
+            return;
+        };
+        let now = Instant::now();
+        let annunciators = MODES.iter().map(|mode| {
+            let on = (mode.sim_state)(&state.autopilot);
+            (mode.led, (on as i32).to_string())
+        });
+        let displays = DISPLAYS
+            .iter()
+            .map(|display| (display.channel, (display.content)(state)));
+
+        for (channel, value) in annunciators.chain(displays) {
+            if conn.supports(channel) && self.outputs.update(channel, &value, now) {
+                conn.send(channel, value);
+            }
+        }
+    }
+
+    /// Forward the detents of a rotary encoder reported with `ENC:<name>:<detents>` to the simulator.
+    fn handle_encoder(&mut self, msg: &str) {
+        let Some((name, detents)) = msg.split_once(':') else {
+            warn!("Malformed encoder message {msg:?}");
+            return;
+        };
+        let Some(encoder) = self.encoders.get_mut(name) else {
+            debug!("Ignoring unconfigured encoder {name:?}");
+            return;
+        };
+        let Ok(detents) = detents.parse() else {
+            warn!("Invalid detents for encoder {name:?}: {detents:?}");
+            return;
+        };
+
+        let events = encoder.turn(detents, self.aircraft_sim_state.as_ref(), Instant::now());
+        for event in events {
+            self.hw_tx
+                .send(Event::SetSimulator(event))
+                .expect("SimConnect thread offline");
+        }
+    }
+
+    fn handle_serial_command(&mut self, cmd: &str, capabilities: &Capabilities) {
+        debug!("Serial port received command: {:?}", cmd);
+        let Some((channel, pressed)) = cmd.split_once(':') else {
+            warn!("Malformed autopilot panel message {cmd:?}");
+            return;
+        };
+        if !capabilities.supports(channel) {
+            warn!("Autopilot panel sent command for unannounced channel {channel:?}");
+        }
+        let Some(mode) = MODES.iter().find(|mode| mode.button == channel) else {
+            return;
+        };
+
+        // The modes toggle, so only the press of a button is forwarded
+        if pressed == "1" {
+            self.hw_tx
+                .send(Event::SetSimulator(mode.event))
+                .expect("SimConnect thread offline");
+        }
+    }
+}
@@ -1,134 +1,590 @@
 use log::debug;
 use log::info;
-use serialport::SerialPort;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::sync::mpsc;
-use std::thread;
-use std::time::Duration;
-use std::time::Instant;
+use log::warn;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 
+use crate::config::AxisCalibration;
+use crate::config::Config;
+use crate::config::FlapDetents;
+use crate::config::GearWarning;
+use crate::config::OutputLimits;
+use crate::config::ReconcilePolicy;
+use crate::config::ResetConfig;
+use crate::encoder::Encoder;
+use crate::gear::GearIndication;
+use crate::gear::GearLogic;
+use crate::output::OutputFilter;
+use crate::panel::Capabilities;
+use crate::panel::FirmwareVersion;
 use crate::panel::Panel;
 use crate::panel::PanelError;
+use crate::panels::line::LineConnection;
+use crate::panels::line::LineProtocol;
+use crate::profile::Profile;
 use crate::sim::AircraftSimState;
+use crate::sim::LightStates;
 use crate::sim::SimClientEvent;
+use crate::simvar::SimVar;
 use crate::Event;
 
-/// The baud rate of the Arduino used for the serial connection.
-const BAUD_RATE: u32 = 115200;
+/// Firmware versions of the EventSim panel that we are compatible with.
+const FIRMWARE_VERSIONS: Range<FirmwareVersion> =
+    FirmwareVersion::new(1, 0, 0)..FirmwareVersion::new(2, 0, 0);
+
+/// Time the panel has to report its switch positions after a `STATE?` request.
+const STATE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Serial protocol of the EventSim panel.
+const PROTOCOL: LineProtocol = LineProtocol {
+    name: "EventSim",
+    // The baud rate of the Arduino used for the serial connection
+    baud_rate: 115200,
+    firmware_versions: FIRMWARE_VERSIONS,
+    required_channels: &[
+        "PARKING_BRAKE",
+        "FRONT_GEAR_LED",
+        "LEFT_GEAR_LED",
+        "RIGHT_GEAR_LED",
+    ],
+    legacy_channels: &[
+        "MISC1",
+        "MISC2",
+        "MISC3",
+        "MISC4",
+        "FLAPS",
+        "PARKING_BRAKE",
+        "LANDING_GEAR",
+        "FRONT_GEAR_LED",
+        "LEFT_GEAR_LED",
+        "RIGHT_GEAR_LED",
+    ],
+};
+
+/// Toggle switch of the panel whose position corresponds to a state in the simulator.
+struct Switch {
+    channel: &'static str,
+    sim_state: fn(&AircraftSimState) -> bool,
+}
+
+/// Switches whose position is compared with the simulator when reconciling.
+///
+/// The `MISC` channels are the light switches in the order of the overhead panel: taxi, landing, nav and strobe on
+/// `MISC1` to `MISC4` as in the original firmware, followed by beacon, wing, logo, recognition, panel and cabin on
+/// `MISC5` to `MISC10`. The firmware has to use the same order, which the README lists with the events of each
+/// channel; `handle_serial_command` and `misc_lights` follow it as well.
+const SWITCHES: &[Switch] = &[
+    Switch {
+        channel: "MISC1",
+        sim_state: |state| state.lights.taxi,
+    },
+    Switch {
+        channel: "MISC2",
+        sim_state: |state| state.lights.landing,
+    },
+    Switch {
+        channel: "MISC3",
+        sim_state: |state| state.lights.nav,
+    },
+    Switch {
+        channel: "MISC4",
+        sim_state: |state| state.lights.strobe,
+    },
+    Switch {
+        channel: "MISC5",
+        sim_state: |state| state.lights.beacon,
+    },
+    Switch {
+        channel: "MISC6",
+        sim_state: |state| state.lights.wing,
+    },
+    Switch {
+        channel: "MISC7",
+        sim_state: |state| state.lights.logo,
+    },
+    Switch {
+        channel: "MISC8",
+        sim_state: |state| state.lights.recognition,
+    },
+    Switch {
+        channel: "MISC9",
+        sim_state: |state| state.lights.panel,
+    },
+    Switch {
+        channel: "MISC10",
+        sim_state: |state| state.lights.cabin,
+    },
+    Switch {
+        channel: "PARKING_BRAKE",
+        sim_state: |state| state.parking_brake_indicator,
+    },
+    Switch {
+        channel: "LANDING_GEAR",
+        sim_state: |state| state.gear_handle_down,
+    },
+];
 
 /// Represents the EventSim Main Panel and holds all state and information.
 #[derive(Debug)]
 pub struct EventSimPanel {
     port: String,
-    connected: bool,
+    reset: ResetConfig,
+    flaps: FlapDetents,
+    axes: HashMap<String, AxisCalibration>,
+    /// Last value sent to the simulator for each axis.
+    axis_values: HashMap<String, i32>,
+    encoders: HashMap<String, Encoder>,
+    /// Gear warning of the configuration, which applies unless the aircraft profile has one.
+    gear_warning: GearWarning,
+    gear: GearLogic,
+    outputs: OutputFilter,
+    /// Gear annunciation that was last sent to the panel.
+    gear_indication: Option<GearIndication>,
     hw_tx: mpsc::Sender<Event>,
     sim_rx: mpsc::Receiver<Event>,
     aircraft_sim_state: Option<AircraftSimState>,
+    reconcile: ReconcilePolicy,
+    /// Last reported position of each switch.
+    switch_positions: HashMap<String, bool>,
+    /// The switch positions have not been reconciled with the simulator yet.
+    reconciling: bool,
+    /// The panel is reporting its switch positions after a `STATE?` request until the deadline.
+    reporting_switches: Option<Instant>,
+    /// Switches that disagreed with the simulator when last reconciling.
+    mismatches: Vec<&'static str>,
+    /// Switches whose mismatch indicator was last switched on.
+    mismatch_indicators: Vec<&'static str>,
+    /// Profile of the loaded aircraft, which replaces the flaps, gear warning and events of the configuration.
+    profile: Option<Arc<Profile>>,
 }
 
 impl Panel for EventSimPanel {
     /// Connect to the panel and run an event loop.
     fn run(&mut self) -> Result<(), PanelError> {
-        debug!(
-            "Attempting to connect to panel on serial port {}",
-            self.port
-        );
-        let mut serial = serialport::new(&self.port, BAUD_RATE)
-            .timeout(Duration::from_millis(10))
-            .open()
-            .map_err(|e| PanelError::SerialOpen(self.port.clone(), e))?;
-
-        // Reset device
-        serial.write_data_terminal_ready(true)?;
-        serial.clear(serialport::ClearBuffer::All)?;
-        // Wait for device to finish resetting
-        thread::sleep(Duration::from_millis(2000));
-
-        let reader = BufReader::with_capacity(1, serial.try_clone()?);
-        let mut line_reader = reader.lines();
-        let mut et = Instant::now();
-
-        // Initiate handshake with the Arduino
-        writeln!(serial, "SYN")?;
+        let mut conn = LineConnection::open(&self.port, self.reset, &PROTOCOL)?;
+        // Write every mismatch indicator once, whatever the panel shows after connecting
+        self.mismatch_indicators = SWITCHES.iter().map(|switch| switch.channel).collect();
+        self.start_reconciling(&mut conn);
 
         loop {
             // Receive control messages
-            if self.connected {
-                match self.sim_rx.try_recv() {
-                    Ok(Event::SetPanel(state)) => {
-                        // Send aircraft state only if it has changed since the last time.
-                        // FIXME: This is very inefficient because we always transmit the full state
-                        if self
-                            .aircraft_sim_state
-                            .as_ref()
-                            .map(|old_state| old_state != &state)
-                            .unwrap_or(true)
-                        {
-                            send_state(&state, &mut serial)?;
-                        }
-                        self.aircraft_sim_state = Some(state);
-                    }
-                    Err(mpsc::TryRecvError::Disconnected) => {
-                        // The simconnect thread cannot exit, we exit always first
-                        unreachable!();
+            match self.sim_rx.try_recv() {
+                Ok(Event::SetPanel(state)) => {
+                    self.aircraft_sim_state = Some(state);
+                    if self.reconciling {
+                        self.reconcile(&mut conn);
                     }
-                    _ => {}
                 }
+                Ok(Event::SimConnected) => {
+                    // The simulator may have loaded a different aircraft state
+                    self.aircraft_sim_state = None;
+                    self.gear_indication = None;
+                    self.outputs.clear();
+                    self.start_reconciling(&mut conn);
+                }
+                Ok(Event::SetProfile(profile)) => {
+                    let warning = profile
+                        .as_ref()
+                        .and_then(|profile| profile.config.gear)
+                        .unwrap_or(self.gear_warning);
+                    self.gear = GearLogic::new(warning);
+                    self.gear_indication = None;
+                    self.profile = profile;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    // The simconnect thread cannot exit, we exit always first
+                    unreachable!();
+                }
+                _ => {}
             }
 
             // Read messages from serial port
-            if let Some(msg) = line_reader.next() {
-                match msg {
-                    Ok(msg) => match msg.as_str() {
-                        "SYN|ACK" => {
-                            writeln!(serial, "ACK")?;
-                            info!(
-                                "Connection with EventSim panel established via {}",
-                                self.port
-                            );
-                            self.connected = true;
-                        }
-                        "RST" => return Err(PanelError::Disconnect),
-                        "PING" => writeln!(serial, "PONG")?,
-                        "PONG" => {}
-                        cmd => self.handle_serial_command(cmd),
-                    },
-                    // Ignore timouts
-                    Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
-                    // Exit on all other errors
-                    Err(e) => return Err(e.into()),
+            if let Some(msg) = conn.poll()? {
+                match msg.as_str() {
+                    "STATE_END" => {
+                        self.reporting_switches = None;
+                        self.reconcile(&mut conn);
+                    }
+                    msg if msg.starts_with("AXIS:") => self.handle_axis(&msg[5..]),
+                    msg if msg.starts_with("ENC:") => self.handle_encoder(&msg[4..]),
+                    "SYNC:1" if self.reconcile == ReconcilePolicy::Prompt => {
+                        self.apply_switch_positions(&mut conn);
+                    }
+                    cmd => self.handle_serial_command(cmd, &conn.capabilities),
                 }
             }
 
-            // Send keepalive packets
-            let now = Instant::now();
-            if now > et + Duration::from_millis(500) {
-                writeln!(serial, "PING")?;
-                et = now;
+            // Reconcile the positions reported so far if the panel never finishes its report
+            if self
+                .reporting_switches
+                .is_some_and(|deadline| Instant::now() > deadline)
+            {
+                warn!("EventSim panel did not finish reporting its switch positions, using the known positions");
+                self.reporting_switches = None;
+                self.reconcile(&mut conn);
             }
+
+            self.send_state(&mut conn);
+
+            // The gear annunciation also changes with time, e.g. when the gear does not follow the lever
+            self.update_gear(&mut conn);
         }
     }
+
+    fn probe(&mut self) -> Result<Capabilities, PanelError> {
+        let conn = LineConnection::open(&self.port, self.reset, &PROTOCOL)?;
+        Ok(conn.capabilities)
+    }
+
+    fn sim_vars(&self) -> Vec<SimVar> {
+        let mut sim_vars = vec![
+            SimVar::Gear,
+            SimVar::ParkingBrake,
+            SimVar::Lights,
+            SimVar::Flaps,
+            // The gear warning depends on the airspeed and the height above the ground
+            SimVar::Airspeed,
+            SimVar::AltitudeAboveGround,
+            SimVar::OnGround,
+            SimVar::Lvars,
+        ];
+        sim_vars.extend(self.encoders.values().filter_map(Encoder::sim_var));
+        sim_vars
+    }
 }
 
 impl EventSimPanel {
     /// Create a new panel instance.
     pub fn new(
         port: impl AsRef<str>,
+        config: &Config,
         hw_tx: mpsc::Sender<Event>,
         sim_rx: mpsc::Receiver<Event>,
     ) -> Self {
         Self {
-            connected: false,
+            reset: config.panel_reset("eventsim"),
+            flaps: config.flaps.clone(),
+            axes: config.panel_axes("eventsim"),
+            axis_values: HashMap::new(),
+            encoders: config
+                .panel_encoders("eventsim")
+                .into_iter()
+                .map(|(name, encoder)| (name, Encoder::new(encoder)))
+                .collect(),
+            gear_warning: config.gear,
+            gear: GearLogic::new(config.gear),
+            outputs: OutputFilter::from_config(config, "eventsim", OutputLimits::default()),
+            gear_indication: None,
             hw_tx,
             sim_rx,
             port: port.as_ref().into(),
             aircraft_sim_state: None,
+            reconcile: config.reconcile,
+            switch_positions: HashMap::new(),
+            reconciling: false,
+            reporting_switches: None,
+            mismatches: Vec::new(),
+            mismatch_indicators: Vec::new(),
+            profile: None,
+        }
+    }
+
+    /// Connect to the panel and pass the raw values of an analog axis to `on_value` until it returns `false`.
+    ///
+    /// The callback is also invoked without a value whenever the panel was idle, so it can react to other input.
+    pub fn stream_axis(
+        &mut self,
+        name: &str,
+        mut on_value: impl FnMut(Option<i32>) -> bool,
+    ) -> Result<(), PanelError> {
+        let mut conn = LineConnection::open(&self.port, self.reset, &PROTOCOL)?;
+
+        loop {
+            let value = conn.poll()?.and_then(|msg| {
+                msg.strip_prefix("AXIS:")
+                    .and_then(|msg| msg.split_once(':'))
+                    .filter(|(axis, _)| *axis == name)
+                    .and_then(|(_, raw)| raw.parse().ok())
+            });
+            if !on_value(value) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Event that moves the flap handle to the given detent of the physical lever, if the detent has a position.
+    fn flaps_event(&self, detent: usize) -> Option<SimClientEvent> {
+        let flaps = self
+            .profile
+            .as_ref()
+            .and_then(|profile| profile.config.flaps.as_ref())
+            .unwrap_or(&self.flaps);
+        let position = *flaps.positions.get(detent)?;
+        let position = (position.clamp(0.0, 100.0) / 100.0 * 16383.0).round();
+        let flaps_set = SimClientEvent::FlapsSet(position as u32);
+        if !flaps.detent_events {
+            return Some(flaps_set);
+        }
+
+        let last = flaps.positions.len() - 1;
+        Some(match detent {
+            0 => SimClientEvent::FlapsRetract,
+            detent if detent == last => SimClientEvent::FlapsExtend,
+            1 => SimClientEvent::Flaps1,
+            2 => SimClientEvent::Flaps2,
+            3 => SimClientEvent::Flaps3,
+            // The simulator has no events for further detents
+            _ => flaps_set,
+        })
+    }
+
+    /// Write the parking brake, flaps and light annunciators that are due according to the output limits.
+    fn send_state(&mut self, conn: &mut LineConnection) {
+        let Some(state) = self.aircraft_sim_state.as_ref() else {
+            return;
+        };
+        let parking_brake =
+            self.switch_state("PARKING_BRAKE", state.parking_brake_indicator, state);
+        let mut outputs = vec![
+            ("PARKING_BRAKE".to_string(), parking_brake as u32),
+            ("FLAPS_INDICATOR".to_string(), state.flaps_handle_index),
+        ];
+        // Mirror the light states on the annunciators next to the switches, if the panel has them
+        for (index, on) in misc_lights(&state.lights).iter().enumerate() {
+            let on = self.switch_state(&format!("MISC{}", index + 1), *on, state);
+            outputs.push((format!("MISC{}_LED", index + 1), on as u32));
+        }
+
+        let now = Instant::now();
+        for (channel, value) in outputs {
+            let value = value.to_string();
+            if conn.supports(&channel) && self.outputs.update(&channel, &value, now) {
+                conn.send(&channel, value);
+            }
+        }
+    }
+
+    /// State of a switch in the simulator, read from the L:var the aircraft profile maps its channel to, if any.
+    fn switch_state(&self, channel: &str, sim_state: bool, state: &AircraftSimState) -> bool {
+        self.profile
+            .as_ref()
+            .and_then(|profile| profile.lvar(channel, state))
+            .map_or(sim_state, |value| value != 0.0)
+    }
+
+    /// Send the gear annunciator states and the gear horn to the panel whenever they change.
+    fn update_gear(&mut self, conn: &mut LineConnection) {
+        let Some(state) = self.aircraft_sim_state.as_ref() else {
+            return;
+        };
+        let lever_down = self.switch_positions.get("LANDING_GEAR").copied();
+        let indication = self.gear.update(state, lever_down, Instant::now());
+        if self.gear_indication == Some(indication) {
+            return;
+        }
+
+        if indication.disagreement {
+            warn!("Landing gear disagrees with the gear lever");
+        }
+        if indication.unsafe_warning {
+            warn!("Landing gear is not down and locked");
+        }
+        conn.send("FRONT_GEAR_LED", indication.front.as_int());
+        conn.send("LEFT_GEAR_LED", indication.left.as_int());
+        conn.send("RIGHT_GEAR_LED", indication.right.as_int());
+        if conn.supports("GEAR_HORN") {
+            conn.send("GEAR_HORN", indication.unsafe_warning as i32);
+        }
+        self.gear_indication = Some(indication);
+    }
+
+    /// Begin comparing the switch positions with the simulator, asking the panel for all positions if it can report them.
+    fn start_reconciling(&mut self, conn: &mut LineConnection) {
+        self.reconciling = true;
+        self.mismatches.clear();
+        if conn.supports("STATE") {
+            conn.send_command("STATE?");
+            self.reporting_switches = Some(Instant::now() + STATE_TIMEOUT);
+        }
+    }
+
+    /// Compare the switch positions with the simulator state and resolve disagreements according to the policy.
+    fn reconcile(&mut self, conn: &mut LineConnection) {
+        let Some(state) = self.aircraft_sim_state.as_ref() else {
+            return;
+        };
+        if self.reporting_switches.is_some() {
+            return;
+        }
+
+        let mismatches: Vec<&'static str> = SWITCHES
+            .iter()
+            // The lever of fixed gear has nothing to agree with
+            .filter(|switch| switch.channel != "LANDING_GEAR" || state.gear_retractable)
+            .filter(|switch| {
+                self.switch_positions
+                    .get(switch.channel)
+                    .is_some_and(|position| {
+                        *position
+                            != self.switch_state(switch.channel, (switch.sim_state)(state), state)
+                    })
+            })
+            .map(|switch| switch.channel)
+            .collect();
+
+        if self.reconcile == ReconcilePolicy::HardwareWins {
+            if mismatches.is_empty() {
+                self.reconciling = false;
+                return;
+            }
+            return self.apply_switch_positions(conn);
+        }
+
+        // Indicate the switches that disagree with the simulator until the pilot moves them
+        self.indicate_mismatches(conn, &mismatches);
+        if !mismatches.is_empty() && mismatches != self.mismatches {
+            match self.reconcile {
+                ReconcilePolicy::Prompt => warn!(
+                    "Panel switches {mismatches:?} disagree with the simulator, move them or press SYNC to apply them"
+                ),
+                _ => warn!("Panel switches {mismatches:?} disagree with the simulator"),
+            }
+        }
+        if mismatches.is_empty() {
+            debug!("Panel switches agree with the simulator");
+            self.reconciling = false;
+        }
+        self.mismatches = mismatches;
+    }
+
+    /// Push the position of every known switch to the simulator.
+    fn apply_switch_positions(&mut self, conn: &mut LineConnection) {
+        info!("Applying panel switch positions to the simulator");
+        let commands: Vec<String> = SWITCHES
+            .iter()
+            .filter_map(|switch| {
+                let position = self.switch_positions.get(switch.channel)?;
+                Some(format!("{}:{}", switch.channel, *position as i32))
+            })
+            .collect();
+        for cmd in commands {
+            self.handle_serial_command(&cmd, &conn.capabilities);
+        }
+
+        self.indicate_mismatches(conn, &[]);
+        self.reconciling = false;
+        self.mismatches.clear();
+    }
+
+    /// Switch the mismatch indicators of the given switches on and all others off, writing only those that change.
+    fn indicate_mismatches(&mut self, conn: &mut LineConnection, mismatches: &[&'static str]) {
+        for switch in SWITCHES {
+            let mismatch = mismatches.contains(&switch.channel);
+            if mismatch == self.mismatch_indicators.contains(&switch.channel) {
+                continue;
+            }
+            let channel = format!("{}_MISMATCH", switch.channel);
+            if conn.supports(&channel) {
+                conn.send(&channel, mismatch as i32);
+            }
+        }
+        self.mismatch_indicators = mismatches.to_vec();
+    }
+
+    /// Forward the raw value of an analog axis reported with `AXIS:<name>:<value>` to the simulator.
+    fn handle_axis(&mut self, msg: &str) {
+        let Some((name, raw)) = msg.split_once(':') else {
+            warn!("Malformed axis message {msg:?}");
+            return;
+        };
+        let Some(calibration) = self.axes.get(name) else {
+            debug!("Ignoring uncalibrated axis {name:?}");
+            return;
+        };
+        let Ok(raw) = raw.parse() else {
+            warn!("Invalid value for axis {name:?}: {raw:?}");
+            return;
+        };
+
+        // Only send the value if the calibrated position changed to avoid flooding the simulator
+        let value = calibration.apply(raw);
+        if self.axis_values.get(name) == Some(&value) {
+            return;
+        }
+        self.hw_tx
+            .send(Event::SetSimulator(calibration.event(value)))
+            .expect("SimConnect thread offline");
+        self.axis_values.insert(name.into(), value);
+    }
+
+    /// Forward the detents of a rotary encoder reported with `ENC:<name>:<detents>` to the simulator.
+    fn handle_encoder(&mut self, msg: &str) {
+        let Some((name, detents)) = msg.split_once(':') else {
+            warn!("Malformed encoder message {msg:?}");
+            return;
+        };
+        let Some(encoder) = self.encoders.get_mut(name) else {
+            debug!("Ignoring unconfigured encoder {name:?}");
+            return;
+        };
+        let Ok(detents) = detents.parse() else {
+            warn!("Invalid detents for encoder {name:?}: {detents:?}");
+            return;
+        };
+
+        let events = encoder.turn(detents, self.aircraft_sim_state.as_ref(), Instant::now());
+        for event in events {
+            self.hw_tx
+                .send(Event::SetSimulator(event))
+                .expect("SimConnect thread offline");
         }
     }
 
-    fn handle_serial_command(&self, cmd: &str) {
+    fn handle_serial_command(&mut self, cmd: &str, capabilities: &Capabilities) {
         debug!("Serial port received command: {:?}", cmd);
+        if let Some((channel, position)) = cmd.split_once(':') {
+            if let Some(switch) = SWITCHES.iter().find(|switch| switch.channel == channel) {
+                self.switch_positions
+                    .insert(switch.channel.into(), position == "1");
+            }
+        }
+        // Positions reported on request only describe the panel, they are reconciled afterwards
+        if self.reporting_switches.is_some() {
+            return;
+        }
+
+        let channel = cmd.split(':').next().unwrap_or(cmd);
+        let channel = channel
+            .strip_suffix("_UP")
+            .or(channel.strip_suffix("_DN"))
+            .unwrap_or(channel);
+        if !capabilities.supports(channel) {
+            warn!("EventSim panel sent command for unannounced channel {channel:?}");
+        }
+
+        if channel == "LANDING_GEAR"
+            && self
+                .aircraft_sim_state
+                .as_ref()
+                .is_some_and(|state| !state.gear_retractable)
+        {
+            warn!("Ignoring the gear lever, the aircraft has fixed gear");
+            return;
+        }
+
+        // The aircraft profile replaces the events of its commands
+        if let Some(event) = self
+            .profile
+            .as_ref()
+            .and_then(|profile| profile.events.get(cmd))
+        {
+            self.hw_tx
+                .send(Event::SetSimulator(*event))
+                .expect("SimConnect thread offline");
+            return;
+        }
         let event = match cmd {
             "MISC1:0" => SimClientEvent::TaxiLightsOff,
             "MISC1:1" => SimClientEvent::TaxiLightsOn,
@@ -138,8 +594,33 @@ impl EventSimPanel {
             "MISC3:1" => SimClientEvent::NavLightsOn,
             "MISC4:0" => SimClientEvent::StrobeLightsOff,
             "MISC4:1" => SimClientEvent::StrobeLightsOn,
+            "MISC5:0" => SimClientEvent::BeaconLightsOff,
+            "MISC5:1" => SimClientEvent::BeaconLightsOn,
+            "MISC6:0" => SimClientEvent::WingLightsOff,
+            "MISC6:1" => SimClientEvent::WingLightsOn,
+            "MISC7:0" => SimClientEvent::LogoLightsOff,
+            "MISC7:1" => SimClientEvent::LogoLightsOn,
+            "MISC8:0" => SimClientEvent::RecognitionLightsOff,
+            "MISC8:1" => SimClientEvent::RecognitionLightsOn,
+            "MISC9:0" => SimClientEvent::PanelLightsOff,
+            "MISC9:1" => SimClientEvent::PanelLightsOn,
+            "MISC10:0" => SimClientEvent::CabinLightsOff,
+            "MISC10:1" => SimClientEvent::CabinLightsOn,
             "FLAPS_UP" => SimClientEvent::FlapsUp,
             "FLAPS_DN" => SimClientEvent::FlapsDown,
+            cmd if cmd.starts_with("FLAPS:") => {
+                let Ok(detent) = cmd[6..].parse() else {
+                    warn!("Ignoring flap lever command with an invalid detent: {cmd:?}");
+                    return;
+                };
+                match self.flaps_event(detent) {
+                    Some(event) => event,
+                    None => {
+                        warn!("No flap handle position configured for lever detent {detent}");
+                        return;
+                    }
+                }
+            }
             "PARKING_BRAKE:0" => SimClientEvent::ParkingBrakeOff,
             "PARKING_BRAKE:1" => SimClientEvent::ParkingBrakeOn,
             "LANDING_GEAR:0" => SimClientEvent::LandingGearUp,
@@ -152,13 +633,18 @@ impl EventSimPanel {
     }
 }
 
-fn send_state(
-    state: &AircraftSimState,
-    tx: &mut Box<dyn SerialPort>,
-) -> Result<(), std::io::Error> {
-    writeln!(tx, "PARKING_BRAKE:{}", state.parking_brake_indicator as i32)?;
-    writeln!(tx, "FRONT_GEAR_LED:{}", state.gear_center_state.as_int())?;
-    writeln!(tx, "LEFT_GEAR_LED:{}", state.gear_left_state.as_int())?;
-    writeln!(tx, "RIGHT_GEAR_LED:{}", state.gear_right_state.as_int())?;
-    Ok(())
+/// Light states in the order of the `MISC` switches that control them.
+fn misc_lights(lights: &LightStates) -> [bool; 10] {
+    [
+        lights.taxi,
+        lights.landing,
+        lights.nav,
+        lights.strobe,
+        lights.beacon,
+        lights.wing,
+        lights.logo,
+        lights.recognition,
+        lights.panel,
+        lights.cabin,
+    ]
 }
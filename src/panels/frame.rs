@@ -0,0 +1,217 @@
+use log::{debug, info, warn};
+use serialport::SerialPort;
+use std::fmt;
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::ops::Range;
+use std::str::FromStr;
+use std::time::Instant;
+
+use crate::config::{OutputLimits, ResetConfig};
+use crate::output::OutputQueue;
+use crate::panel::{open_serial, Capabilities, FirmwareVersion, PanelError};
+
+/// Limits of the outputs that are not configured, which keep the slow serial link of the gauges from being flooded.
+pub const DEFAULT_LIMITS: OutputLimits = OutputLimits {
+    max_rate: Some(20.0),
+    deadband: None,
+    max_staleness_ms: None,
+};
+
+/// Description of a panel that speaks the `Type<..>::Target<..>::Content<..>::Origin<..>;` frame protocol.
+#[derive(Debug)]
+pub struct FrameProtocol<'a> {
+    /// Name the firmware announces itself with.
+    pub device: &'a str,
+    pub baud_rate: u32,
+    /// Firmware versions of the panel that we are compatible with.
+    pub firmware_versions: Range<FirmwareVersion>,
+    /// Channels that every firmware must provide, because we always write them.
+    pub required_channels: &'a [&'a str],
+}
+
+/// Message of the frame protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Direction of the message, `I-A` from the interface to the Arduino and `A-I` the other way round.
+    pub kind: String,
+    pub target: String,
+    pub content: String,
+    pub origin: String,
+}
+
+impl Frame {
+    /// Frame that sets the content of an instrument of the panel.
+    pub fn to_instrument(target: &str, content: impl fmt::Display) -> Self {
+        Self {
+            kind: "I-A".into(),
+            target: target.into(),
+            content: content.to_string(),
+            origin: "Interface".into(),
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Type<{}>::Target<{}>::Content<{}>::Origin<{}>;",
+            self.kind, self.target, self.content, self.origin
+        )
+    }
+}
+
+impl FromStr for Frame {
+    type Err = PanelError;
+
+    fn from_str(msg: &str) -> Result<Self, Self::Err> {
+        let mut frame = Frame {
+            kind: String::new(),
+            target: String::new(),
+            content: String::new(),
+            origin: String::new(),
+        };
+        for (key, value) in fields(msg)? {
+            match key {
+                "Type" => frame.kind = value.into(),
+                "Target" => frame.target = value.into(),
+                "Content" => frame.content = value.into(),
+                "Origin" => frame.origin = value.into(),
+                _ => debug!("Ignoring unknown frame field '{key}'"),
+            }
+        }
+        Ok(frame)
+    }
+}
+
+/// Established connection with a panel that speaks the frame protocol.
+pub struct FrameConnection {
+    serial: Box<dyn SerialPort>,
+    reader: BufReader<Box<dyn SerialPort>>,
+    /// Bytes of a frame that has not been received completely yet.
+    buf: Vec<u8>,
+    /// Channels and firmware version announced by the panel.
+    pub capabilities: Capabilities,
+    /// Frames waiting to be written to the panel.
+    queue: OutputQueue,
+}
+
+impl FrameConnection {
+    /// Open the serial port, reset the device and verify its banner.
+    pub fn open(
+        port: &str,
+        reset: ResetConfig,
+        protocol: &FrameProtocol,
+    ) -> Result<Self, PanelError> {
+        let serial = open_serial(port, protocol.baud_rate, reset)?;
+
+        // Setup reader for initial device message
+        let reader = BufReader::with_capacity(1, serial.try_clone()?);
+        let mut connection = Self {
+            serial,
+            reader,
+            buf: Vec::new(),
+            capabilities: Capabilities::new(protocol.firmware_versions.start, []),
+            queue: OutputQueue::new(protocol.device, protocol.baud_rate),
+        };
+
+        // Verify that we are connected to the correct arduino, the banner may still be arriving
+        let deadline = Instant::now() + reset.ready_timeout;
+        let initial_msg = loop {
+            if let Some(msg) = connection.read_message()? {
+                break msg;
+            }
+            if Instant::now() >= deadline {
+                return Err(PanelError::WrongDevice);
+            }
+        };
+        debug!("Initial {} message: '{initial_msg}'", protocol.device);
+        connection.capabilities = parse_banner(&initial_msg, protocol)?;
+        connection
+            .capabilities
+            .check(&protocol.firmware_versions, protocol.required_channels)?;
+        info!(
+            "Connection with {} panel established via {port} (firmware {})",
+            protocol.device, connection.capabilities.firmware
+        );
+
+        Ok(connection)
+    }
+
+    /// Queue a frame, replacing a frame for the same target that was not written yet.
+    pub fn send(&mut self, frame: &Frame) {
+        self.queue.push(&frame.target, format!("{frame}\n"));
+    }
+
+    /// Write the queued frames and receive the next frame of the panel, if one arrived in time.
+    pub fn poll(&mut self) -> Result<Option<Frame>, PanelError> {
+        self.queue.flush(&mut self.serial)?;
+        let Some(msg) = self.read_message()? else {
+            return Ok(None);
+        };
+        match msg.parse() {
+            Ok(frame) => Ok(Some(frame)),
+            Err(_) => {
+                warn!("Ignoring malformed frame {msg:?}");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Read a message up to its terminating `;`, returning `None` until it is complete.
+    fn read_message(&mut self) -> Result<Option<String>, PanelError> {
+        match self.reader.read_until(b';', &mut self.buf) {
+            // Bytes read before a timeout are kept in the buffer for the next attempt
+            Err(e) if e.kind() == ErrorKind::TimedOut => Ok(None),
+            Err(e) => Err(e.into()),
+            Ok(_) if self.buf.last() != Some(&b';') => Ok(None),
+            Ok(_) => {
+                let msg = String::from_utf8_lossy(&self.buf).trim().to_string();
+                self.buf.clear();
+                Ok(Some(msg))
+            }
+        }
+    }
+}
+
+/// Split a message into its `Key<value>` fields.
+fn fields(msg: &str) -> Result<Vec<(&str, &str)>, PanelError> {
+    msg.trim()
+        .trim_end_matches(';')
+        .split("::")
+        .map(|field| {
+            field
+                .strip_suffix('>')
+                .and_then(|field| field.split_once('<'))
+                .ok_or(PanelError::WrongDevice)
+        })
+        .collect()
+}
+
+/// Parse the banner the firmware sends after a reset.
+///
+/// Current firmware announces itself with `Name<..>::Firmware<..>::Channels<..,..>;`, while firmware released before
+/// the capability discovery only sends `Name<..>;` and is treated as the oldest compatible version.
+fn parse_banner(msg: &str, protocol: &FrameProtocol) -> Result<Capabilities, PanelError> {
+    let mut name = None;
+    let mut firmware = None;
+    let mut channels = None;
+    for (key, value) in fields(msg)? {
+        match key {
+            "Name" => name = Some(value),
+            "Firmware" => firmware = Some(value.parse()?),
+            "Channels" => channels = Some(value),
+            _ => debug!("Ignoring unknown banner field '{key}'"),
+        }
+    }
+
+    if name != Some(protocol.device) {
+        return Err(PanelError::WrongDevice);
+    }
+    let required = protocol.required_channels.iter().copied();
+    Ok(match (firmware, channels) {
+        (Some(firmware), Some(channels)) => Capabilities::new(firmware, channels.split(',')),
+        (Some(firmware), None) => Capabilities::new(firmware, required),
+        _ => Capabilities::new(protocol.firmware_versions.start, required),
+    })
+}
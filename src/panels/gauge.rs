@@ -0,0 +1,194 @@
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use crate::config::{Config, GaugeConfig, GaugeSource, ResetConfig};
+use crate::encoder::Encoder;
+use crate::needle::Needle;
+use crate::output::OutputFilter;
+use crate::panel::{Capabilities, FirmwareVersion, Panel, PanelError};
+use crate::panels::frame::{Frame, FrameConnection, FrameProtocol, DEFAULT_LIMITS};
+use crate::profile::Profile;
+use crate::sim::AircraftSimState;
+use crate::simvar::SimVar;
+use crate::Event;
+
+/// The baud rate of the Arduino used for the serial connection.
+const BAUD_RATE: u32 = 38400;
+
+/// Firmware versions of the stepper gauges that we are compatible with.
+const FIRMWARE_VERSIONS: Range<FirmwareVersion> =
+    FirmwareVersion::new(1, 0, 0)..FirmwareVersion::new(2, 0, 0);
+
+/// Represents a panel of stepper gauges like the altimeter, attitude, heading, VSI and turn coordinator.
+///
+/// Each gauge is a target of the frame protocol and displays one simulator value. Encoders like the Kohlsman knob of
+/// the altimeter report `Type<A-I>::Target<Interface>::Content<detents>::Origin<encoder>;`.
+#[derive(Debug)]
+pub struct GaugePanel {
+    name: String,
+    port: String,
+    reset: ResetConfig,
+    device: String,
+    /// Gauges of the panel configuration by target name, which apply unless the aircraft profile replaces them.
+    configured: HashMap<String, GaugeConfig>,
+    /// Needles of the gauges by target name.
+    gauges: HashMap<String, Needle>,
+    /// Sources of the configured gauges and of the gauges of the aircraft profiles.
+    sources: Vec<GaugeSource>,
+    outputs: OutputFilter,
+    encoders: HashMap<String, Encoder>,
+    hw_tx: mpsc::Sender<Event>,
+    sim_rx: mpsc::Receiver<Event>,
+    aircraft_sim_state: Option<AircraftSimState>,
+}
+
+impl Panel for GaugePanel {
+    /// Connect to the panel and run an event loop.
+    fn run(&mut self) -> Result<(), PanelError> {
+        let mut conn = self.connect()?;
+
+        loop {
+            // Receive control messages
+            match self.sim_rx.try_recv() {
+                Ok(Event::SetPanel(state)) => {
+                    self.aircraft_sim_state = Some(state);
+                }
+                Ok(Event::SimConnected) => {
+                    // Send all gauges again once the simulator sends its state
+                    self.aircraft_sim_state = None;
+                    for needle in self.gauges.values_mut() {
+                        needle.reset();
+                    }
+                    self.outputs.clear();
+                }
+                Ok(Event::SetProfile(profile)) => self.apply_profile(profile.as_deref()),
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    // The simconnect thread cannot exit, we exit always first
+                    unreachable!();
+                }
+                _ => {}
+            }
+
+            // Read messages from serial port
+            if let Some(frame) = conn.poll()? {
+                self.handle_frame(&frame);
+            }
+
+            self.update_gauges(&mut conn);
+        }
+    }
+
+    fn probe(&mut self) -> Result<Capabilities, PanelError> {
+        let conn = self.connect()?;
+        Ok(conn.capabilities)
+    }
+
+    fn sim_vars(&self) -> Vec<SimVar> {
+        let mut sim_vars: Vec<SimVar> = self.sources.iter().map(GaugeSource::sim_var).collect();
+        sim_vars.extend(self.encoders.values().filter_map(Encoder::sim_var));
+        sim_vars
+    }
+}
+
+impl GaugePanel {
+    /// Create a new panel instance for the panel with the given name in the configuration.
+    pub fn new(
+        name: &str,
+        port: impl AsRef<str>,
+        config: &Config,
+        hw_tx: mpsc::Sender<Event>,
+        sim_rx: mpsc::Receiver<Event>,
+    ) -> Self {
+        let configured = config.panel_gauges(name);
+        Self {
+            name: name.into(),
+            port: port.as_ref().into(),
+            reset: config.panel_reset(name),
+            device: config.panel_device(name),
+            gauges: configured
+                .iter()
+                .map(|(target, config)| (target.clone(), Needle::new(config.clone())))
+                .collect(),
+            configured,
+            sources: config.panel_gauge_sources(name),
+            outputs: OutputFilter::from_config(config, name, DEFAULT_LIMITS),
+            encoders: config
+                .panel_encoders(name)
+                .into_iter()
+                .map(|(name, encoder)| (name, Encoder::new(encoder)))
+                .collect(),
+            hw_tx,
+            sim_rx,
+            aircraft_sim_state: None,
+        }
+    }
+
+    /// Open the serial port, reset the device and verify that it drives all configured gauges.
+    fn connect(&self) -> Result<FrameConnection, PanelError> {
+        let targets: Vec<&str> = self.gauges.keys().map(String::as_str).collect();
+        let protocol = FrameProtocol {
+            device: &self.device,
+            baud_rate: BAUD_RATE,
+            firmware_versions: FIRMWARE_VERSIONS,
+            required_channels: &targets,
+        };
+        FrameConnection::open(&self.port, self.reset, &protocol)
+    }
+
+    /// Send the gauges whose content is due according to the output limits.
+    fn update_gauges(&mut self, conn: &mut FrameConnection) {
+        let Some(state) = self.aircraft_sim_state.as_ref() else {
+            return;
+        };
+        let now = Instant::now();
+        for (target, needle) in &mut self.gauges {
+            let content = needle.update(state, now).to_string();
+            if self.outputs.update(target, &content, now) {
+                conn.send(&Frame::to_instrument(target, content));
+            }
+        }
+    }
+
+    /// Calibrate the gauges as the aircraft profile demands, or as configured without a profile.
+    fn apply_profile(&mut self, profile: Option<&Profile>) {
+        for (target, configured) in &self.configured {
+            let config = profile
+                .and_then(|profile| profile.gauge(&self.name, target))
+                .unwrap_or(configured);
+            // Keep the needle position of gauges whose calibration stays the same
+            if self.gauges[target].config != *config {
+                self.gauges
+                    .insert(target.clone(), Needle::new(config.clone()));
+            }
+        }
+    }
+
+    /// Forward the detents of an encoder to the simulator.
+    fn handle_frame(&mut self, frame: &Frame) {
+        debug!("Serial port received frame: {:?}", frame);
+        if frame.kind != "A-I" {
+            return;
+        }
+        let Some(encoder) = self.encoders.get_mut(&frame.origin) else {
+            debug!("Ignoring unconfigured encoder {:?}", frame.origin);
+            return;
+        };
+        let Ok(detents) = frame.content.parse() else {
+            warn!(
+                "Invalid detents for encoder {:?}: {:?}",
+                frame.origin, frame.content
+            );
+            return;
+        };
+
+        let events = encoder.turn(detents, self.aircraft_sim_state.as_ref(), Instant::now());
+        for event in events {
+            self.hw_tx
+                .send(Event::SetSimulator(event))
+                .expect("SimConnect thread offline");
+        }
+    }
+}
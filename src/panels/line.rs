@@ -0,0 +1,490 @@
+use log::{debug, info};
+use serialport::SerialPort;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::ops::{Range, RangeInclusive};
+use std::time::{Duration, Instant};
+
+use crate::config::ResetConfig;
+use crate::output::OutputQueue;
+use crate::panel::{open_serial, Capabilities, FirmwareVersion, PanelError};
+
+/// Time to wait for a `SYN|ACK` before the `SYN` is sent again.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Number of `SYN` messages sent before we give up on the device, once its `ready_timeout` has passed.
+const HANDSHAKE_ATTEMPTS: u32 = 3;
+
+/// Protocol versions of the line-based firmware that we are able to speak.
+///
+/// Firmware that answers with a plain `SYN|ACK` predates versioning and speaks version 1.
+/// Starting with version 2 the firmware reports its capabilities when asked with `CAPS?`.
+const PROTOCOL_VERSIONS: RangeInclusive<u32> = 1..=2;
+
+/// Interval in which keepalive packets are sent to the panel.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Description of a panel that speaks the line-based protocol with `<CHANNEL>:<value>` messages.
+#[derive(Debug)]
+pub struct LineProtocol {
+    /// Name of the panel used in log messages.
+    pub name: &'static str,
+    pub baud_rate: u32,
+    /// Firmware versions of the panel that we are compatible with.
+    pub firmware_versions: Range<FirmwareVersion>,
+    /// Channels that every firmware must provide, because we always write them.
+    pub required_channels: &'static [&'static str],
+    /// Channels of the firmware released before the capability discovery was introduced.
+    pub legacy_channels: &'static [&'static str],
+}
+
+/// State of the connection handshake with the panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Handshake {
+    /// A `SYN` was sent and we are waiting for the `SYN|ACK` until the deadline.
+    SynSent { attempt: u32, deadline: Instant },
+    /// The panel was asked for its capabilities and we are waiting for them until the deadline.
+    Discovering {
+        version: u32,
+        firmware: Option<FirmwareVersion>,
+        deadline: Instant,
+    },
+    /// The panel answered and speaks the negotiated protocol version.
+    Established { version: u32 },
+}
+
+/// Established connection with a panel that speaks the line-based protocol.
+pub struct LineConnection {
+    serial: Box<dyn SerialPort>,
+    reader: BufReader<Box<dyn SerialPort>>,
+    /// Bytes of the message being received, which are kept when a read times out in the middle of it.
+    line: Vec<u8>,
+    handshake: Handshake,
+    /// Channels and firmware version announced by the panel.
+    pub capabilities: Capabilities,
+    /// Time the last keepalive packet was sent.
+    last_ping: Instant,
+    /// Messages waiting to be written to the panel.
+    queue: OutputQueue,
+}
+
+impl LineConnection {
+    /// Open the serial port, reset the device and complete the handshake.
+    pub fn open(
+        port: &str,
+        reset: ResetConfig,
+        protocol: &LineProtocol,
+    ) -> Result<Self, PanelError> {
+        let serial = open_serial(port, protocol.baud_rate, reset)?;
+        let ready_deadline = Instant::now() + reset.ready_timeout;
+        Self::connect(serial, port, ready_deadline, protocol)
+    }
+
+    /// Complete the handshake with a device that was just reset.
+    ///
+    /// The line firmware stays silent until it receives a `SYN`, so instead of waiting for the device to announce
+    /// itself, the `SYN` is repeated until the sketch answers after the bootloader. Whatever the bootloader sends
+    /// before is ignored.
+    fn connect(
+        serial: Box<dyn SerialPort>,
+        port: &str,
+        ready_deadline: Instant,
+        protocol: &LineProtocol,
+    ) -> Result<Self, PanelError> {
+        let reader = BufReader::with_capacity(1, serial.try_clone()?);
+        let mut connection = Self {
+            serial,
+            reader,
+            line: Vec::new(),
+            handshake: Handshake::SynSent {
+                attempt: 0,
+                deadline: Instant::now(),
+            },
+            capabilities: Capabilities::new(protocol.firmware_versions.start, []),
+            last_ping: Instant::now(),
+            queue: OutputQueue::new(protocol.name, protocol.baud_rate),
+        };
+
+        // Initiate handshake with the Arduino, the SYN is repeated until the sketch is running after the reset
+        connection.send_syn(1)?;
+
+        while !connection.is_connected() {
+            // Retransmit the handshake or give up if the device never answers
+            match connection.handshake {
+                Handshake::SynSent { attempt, deadline } if Instant::now() > deadline => {
+                    if attempt >= HANDSHAKE_ATTEMPTS && Instant::now() > ready_deadline {
+                        return Err(PanelError::WrongDevice);
+                    }
+                    debug!("No handshake reply from {port}, retrying");
+                    connection.send_syn(attempt + 1)?;
+                }
+                Handshake::Discovering { deadline, .. } if Instant::now() > deadline => {
+                    return Err(PanelError::WrongDevice);
+                }
+                _ => {}
+            }
+
+            match connection.read_line()? {
+                Some(msg) if msg.starts_with("SYN|ACK") => {
+                    connection.handle_syn_ack(&msg, protocol)?;
+                }
+                Some(msg) if msg.starts_with("FIRMWARE:") => connection.handle_firmware(&msg)?,
+                Some(msg) if msg.starts_with("CAPS:") => connection.handle_caps(&msg, protocol)?,
+                Some(msg) => debug!("Ignoring message before handshake: {:?}", msg),
+                None => {}
+            }
+        }
+
+        let Handshake::Established { version } = connection.handshake else {
+            unreachable!();
+        };
+        info!(
+            "Connection with {} panel established via {port} (protocol version {version}, firmware {})",
+            protocol.name, connection.capabilities.firmware
+        );
+        debug!(
+            "{} panel channels: {:?}",
+            protocol.name, connection.capabilities.channels
+        );
+        Ok(connection)
+    }
+
+    /// Whether the panel announced the given channel.
+    pub fn supports(&self, channel: &str) -> bool {
+        self.capabilities.supports(channel)
+    }
+
+    /// Queue the value of an output channel, replacing a value of the channel that was not written yet.
+    pub fn send(&mut self, channel: &str, value: impl fmt::Display) {
+        self.queue.push(channel, format!("{channel}:{value}\n"));
+    }
+
+    /// Queue a command without a value like `STATE?`.
+    pub fn send_command(&mut self, cmd: &str) {
+        self.queue.push(cmd, format!("{cmd}\n"));
+    }
+
+    /// Write the queued messages and receive the next message of the panel, if one arrived in time.
+    ///
+    /// Keepalive packets and retransmitted handshakes are handled here and never returned.
+    pub fn poll(&mut self) -> Result<Option<String>, PanelError> {
+        // Send keepalive packets
+        let now = Instant::now();
+        if now > self.last_ping + KEEPALIVE_INTERVAL {
+            self.send_command("PING");
+            self.last_ping = now;
+        }
+        self.queue.flush(&mut self.serial)?;
+
+        Ok(match self.read_line()? {
+            // Answer a retransmitted SYN again
+            Some(msg) if msg.starts_with("SYN|ACK") => {
+                self.send_command("ACK");
+                None
+            }
+            Some(msg) if msg == "RST" => return Err(PanelError::Disconnect),
+            Some(msg) if msg == "PING" => {
+                self.send_command("PONG");
+                None
+            }
+            Some(msg) if msg == "PONG" => None,
+            msg => msg,
+        })
+    }
+
+    /// Read a single line from the serial port, returning `None` on timeouts.
+    ///
+    /// Noise in front of a message, like the bytes of the bootloader after a reset, is stripped.
+    fn read_line(&mut self) -> Result<Option<String>, PanelError> {
+        match self.reader.read_until(b'\n', &mut self.line) {
+            Ok(_) if self.line.ends_with(b"\n") => {
+                let line = mem::take(&mut self.line);
+                let msg = String::from_utf8_lossy(&line);
+                Ok(Some(
+                    msg.trim_start_matches(|c: char| !c.is_ascii_graphic())
+                        .trim_end_matches(['\r', '\n'])
+                        .to_string(),
+                ))
+            }
+            // Wait for the rest of an incomplete line
+            Ok(_) => Ok(None),
+            // Ignore timouts
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Ok(None),
+            // Exit on all other errors
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether the handshake with the panel has been completed.
+    fn is_connected(&self) -> bool {
+        matches!(self.handshake, Handshake::Established { .. })
+    }
+
+    /// Send a `SYN` to the panel and wait for its reply.
+    fn send_syn(&mut self, attempt: u32) -> Result<(), PanelError> {
+        writeln!(self.serial, "SYN")?;
+        self.handshake = Handshake::SynSent {
+            attempt,
+            deadline: Instant::now() + HANDSHAKE_TIMEOUT,
+        };
+        Ok(())
+    }
+
+    /// Complete the handshake after the panel answered with `SYN|ACK[:<version>]`.
+    fn handle_syn_ack(&mut self, msg: &str, protocol: &LineProtocol) -> Result<(), PanelError> {
+        let version = match msg.strip_prefix("SYN|ACK") {
+            Some("") => 1,
+            Some(version) => version
+                .strip_prefix(':')
+                .and_then(|version| version.parse().ok())
+                .ok_or(PanelError::WrongDevice)?,
+            None => return Err(PanelError::WrongDevice),
+        };
+        if !PROTOCOL_VERSIONS.contains(&version) {
+            return Err(PanelError::UnsupportedProtocol(version));
+        }
+
+        // A retransmitted SYN may be answered twice, so only continue after the first reply
+        writeln!(self.serial, "ACK")?;
+        if let Handshake::SynSent { .. } = self.handshake {
+            if version >= 2 {
+                writeln!(self.serial, "CAPS?")?;
+                self.handshake = Handshake::Discovering {
+                    version,
+                    firmware: None,
+                    deadline: Instant::now() + HANDSHAKE_TIMEOUT,
+                };
+            } else {
+                let capabilities = Capabilities::new(
+                    protocol.firmware_versions.start,
+                    protocol.legacy_channels.iter().copied(),
+                );
+                self.establish(version, capabilities, protocol)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the firmware version reported with `FIRMWARE:<major>.<minor>.<patch>`.
+    fn handle_firmware(&mut self, msg: &str) -> Result<(), PanelError> {
+        if let Handshake::Discovering { firmware, .. } = &mut self.handshake {
+            *firmware = Some(msg.trim_start_matches("FIRMWARE:").parse()?);
+        }
+        Ok(())
+    }
+
+    /// Complete the handshake with the channels reported with `CAPS:<channel>,<channel>,...`.
+    fn handle_caps(&mut self, msg: &str, protocol: &LineProtocol) -> Result<(), PanelError> {
+        if let Handshake::Discovering {
+            version, firmware, ..
+        } = self.handshake
+        {
+            let firmware = firmware.ok_or(PanelError::WrongDevice)?;
+            let channels = msg.trim_start_matches("CAPS:").split(',');
+            self.establish(version, Capabilities::new(firmware, channels), protocol)?;
+        }
+        Ok(())
+    }
+
+    /// Verify the capabilities of the panel and mark the connection as established.
+    fn establish(
+        &mut self,
+        version: u32,
+        capabilities: Capabilities,
+        protocol: &LineProtocol,
+    ) -> Result<(), PanelError> {
+        capabilities.check(&protocol.firmware_versions, protocol.required_channels)?;
+        self.handshake = Handshake::Established { version };
+        self.capabilities = capabilities;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serialport::{ClearBuffer, DataBits, FlowControl, Parity, StopBits};
+    use std::collections::VecDeque;
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
+    const PROTOCOL: LineProtocol = LineProtocol {
+        name: "Test",
+        baud_rate: 115200,
+        firmware_versions: FirmwareVersion::new(1, 0, 0)..FirmwareVersion::new(2, 0, 0),
+        required_channels: &["LED"],
+        legacy_channels: &[],
+    };
+
+    /// Serial port of line firmware that answers the handshake, shared between the clones of the port.
+    #[derive(Clone, Default)]
+    struct Device(Arc<Mutex<DeviceState>>);
+
+    #[derive(Default)]
+    struct DeviceState {
+        /// Number of `SYN` messages the bootloader swallows before the sketch runs.
+        ignored_syns: u32,
+        input: Vec<u8>,
+        output: VecDeque<u8>,
+    }
+
+    impl Device {
+        /// Device whose bootloader left the given bytes in the receive buffer.
+        fn after_reset(bootloader_output: &[u8], ignored_syns: u32) -> Self {
+            Self(Arc::new(Mutex::new(DeviceState {
+                ignored_syns,
+                output: bootloader_output.iter().copied().collect(),
+                ..Default::default()
+            })))
+        }
+    }
+
+    impl Read for Device {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut state = self.0.lock().unwrap();
+            if state.output.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let length = buf.len().min(state.output.len());
+            for (byte, sent) in buf.iter_mut().zip(state.output.drain(..length)) {
+                *byte = sent;
+            }
+            Ok(length)
+        }
+    }
+
+    impl Write for Device {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut state = self.0.lock().unwrap();
+            state.input.extend_from_slice(buf);
+            while let Some(end) = state.input.iter().position(|byte| *byte == b'\n') {
+                let msg: Vec<u8> = state.input.drain(..=end).collect();
+                let reply: &[u8] = match &msg[..] {
+                    b"SYN\n" if state.ignored_syns > 0 => {
+                        state.ignored_syns -= 1;
+                        b""
+                    }
+                    b"SYN\n" => b"SYN|ACK:2\r\n",
+                    b"CAPS?\n" => b"FIRMWARE:1.2.0\r\nCAPS:LED,MISC1\r\n",
+                    _ => b"",
+                };
+                state.output.extend(reply);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SerialPort for Device {
+        fn name(&self) -> Option<String> {
+            None
+        }
+        fn baud_rate(&self) -> serialport::Result<u32> {
+            Ok(PROTOCOL.baud_rate)
+        }
+        fn data_bits(&self) -> serialport::Result<DataBits> {
+            Ok(DataBits::Eight)
+        }
+        fn flow_control(&self) -> serialport::Result<FlowControl> {
+            Ok(FlowControl::None)
+        }
+        fn parity(&self) -> serialport::Result<Parity> {
+            Ok(Parity::None)
+        }
+        fn stop_bits(&self) -> serialport::Result<StopBits> {
+            Ok(StopBits::One)
+        }
+        fn timeout(&self) -> Duration {
+            Duration::ZERO
+        }
+        fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_parity(&mut self, _: Parity) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn set_timeout(&mut self, _: Duration) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+            Ok(true)
+        }
+        fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+            Ok(true)
+        }
+        fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+            Ok(false)
+        }
+        fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+            Ok(true)
+        }
+        fn bytes_to_read(&self) -> serialport::Result<u32> {
+            Ok(self.0.lock().unwrap().output.len() as u32)
+        }
+        fn bytes_to_write(&self) -> serialport::Result<u32> {
+            Ok(0)
+        }
+        fn clear(&self, _: ClearBuffer) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+            Ok(Box::new(self.clone()))
+        }
+        fn set_break(&self) -> serialport::Result<()> {
+            Ok(())
+        }
+        fn clear_break(&self) -> serialport::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn connect(device: &Device) -> Result<LineConnection, PanelError> {
+        LineConnection::connect(
+            Box::new(device.clone()),
+            "test",
+            Instant::now() + Duration::from_secs(2),
+            &PROTOCOL,
+        )
+    }
+
+    #[test]
+    fn handshake_ignores_bootloader_bytes() {
+        // Optiboot answers in STK500 bytes, which are followed by the reply of the sketch on the same line
+        let device = Device::after_reset(&[0x00, 0xf0, 0x14, 0x10, b'\n', 0xfe, 0x14], 0);
+        let connection = connect(&device).unwrap();
+
+        assert_eq!(connection.handshake, Handshake::Established { version: 2 });
+        assert_eq!(
+            connection.capabilities.firmware,
+            FirmwareVersion::new(1, 2, 0)
+        );
+        assert!(connection.supports("MISC1"));
+    }
+
+    #[test]
+    fn handshake_repeats_syn_until_sketch_runs() {
+        let device = Device::after_reset(&[0x14, 0x10], 1);
+        let connection = connect(&device).unwrap();
+
+        assert_eq!(connection.handshake, Handshake::Established { version: 2 });
+    }
+}
@@ -1,2 +1,8 @@
 pub mod airspeedindicator;
+pub mod autopilot;
 pub mod eventsim;
+pub mod frame;
+pub mod gauge;
+pub mod line;
+pub mod radio;
+pub mod transponder;
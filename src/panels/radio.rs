@@ -0,0 +1,203 @@
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use crate::config::{ChannelSpacing, Config, OutputLimits, ResetConfig};
+use crate::output::OutputFilter;
+use crate::panel::{Capabilities, FirmwareVersion, Panel, PanelError};
+use crate::panels::line::{LineConnection, LineProtocol};
+use crate::radio::Radio;
+use crate::sim::{AircraftSimState, Frequency};
+use crate::simvar::SimVar;
+use crate::Event;
+
+/// Serial protocol of the radio panel.
+const PROTOCOL: LineProtocol = LineProtocol {
+    name: "radio",
+    baud_rate: 115200,
+    firmware_versions: FirmwareVersion::new(1, 0, 0)..FirmwareVersion::new(2, 0, 0),
+    required_channels: &["COM1_ACTIVE", "COM1_STANDBY"],
+    // The panel was released after the capability discovery, so there is no legacy firmware
+    legacy_channels: &[],
+};
+
+/// Represents the radio stack with the active and standby frequencies of the COM and NAV radios.
+///
+/// Every radio `<RADIO>` has the displays `<RADIO>_ACTIVE` and `<RADIO>_STANDBY`, a swap button `<RADIO>_SWAP` and a
+/// dual encoder reported as `ENC:<RADIO>_WHOLE:<detents>` and `ENC:<RADIO>_FRACT:<detents>`.
+#[derive(Debug)]
+pub struct RadioPanel {
+    port: String,
+    reset: ResetConfig,
+    com_spacing: ChannelSpacing,
+    hw_tx: mpsc::Sender<Event>,
+    sim_rx: mpsc::Receiver<Event>,
+    aircraft_sim_state: Option<AircraftSimState>,
+    /// Standby frequencies that were tuned but not yet reported back by the simulator.
+    tuned: HashMap<Radio, Frequency>,
+    outputs: OutputFilter,
+}
+
+impl Panel for RadioPanel {
+    /// Connect to the panel and run an event loop.
+    fn run(&mut self) -> Result<(), PanelError> {
+        let mut conn = LineConnection::open(&self.port, self.reset, &PROTOCOL)?;
+
+        loop {
+            // Receive control messages
+            match self.sim_rx.try_recv() {
+                Ok(Event::SetPanel(state)) => {
+                    // Tuning continues from the simulator once it caught up with the encoder
+                    self.tuned
+                        .retain(|radio, frequency| radio.standby(&state.radios) != *frequency);
+                    self.aircraft_sim_state = Some(state);
+                }
+                Ok(Event::SimConnected) => {
+                    // Write all displays again once the simulator sends its state
+                    self.aircraft_sim_state = None;
+                    self.tuned.clear();
+                    self.outputs.clear();
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    // The simconnect thread cannot exit, we exit always first
+                    unreachable!();
+                }
+                _ => {}
+            }
+
+            // Read messages from serial port
+            if let Some(msg) = conn.poll()? {
+                match msg.as_str() {
+                    msg if msg.starts_with("ENC:") => self.handle_encoder(&msg[4..]),
+                    cmd => self.handle_serial_command(cmd, &conn.capabilities),
+                }
+            }
+
+            self.send_state(&mut conn);
+        }
+    }
+
+    fn probe(&mut self) -> Result<Capabilities, PanelError> {
+        let conn = LineConnection::open(&self.port, self.reset, &PROTOCOL)?;
+        Ok(conn.capabilities)
+    }
+
+    fn sim_vars(&self) -> Vec<SimVar> {
+        vec![SimVar::Radios]
+    }
+}
+
+impl RadioPanel {
+    /// Create a new panel instance.
+    pub fn new(
+        port: impl AsRef<str>,
+        config: &Config,
+        hw_tx: mpsc::Sender<Event>,
+        sim_rx: mpsc::Receiver<Event>,
+    ) -> Self {
+        Self {
+            port: port.as_ref().into(),
+            reset: config.panel_reset("radio"),
+            com_spacing: config.panel_com_spacing("radio"),
+            hw_tx,
+            sim_rx,
+            aircraft_sim_state: None,
+            tuned: HashMap::new(),
+            outputs: OutputFilter::from_config(config, "radio", OutputLimits::default()),
+        }
+    }
+
+    /// Write the frequencies that are due according to the output limits.
+    fn send_state(&mut self, conn: &mut LineConnection) {
+        let Some(state) = self.aircraft_sim_state.as_ref() else {
+            return;
+        };
+        let now = Instant::now();
+        for radio in Radio::ALL {
+            // Show the tuned frequency right away instead of waiting for the simulator
+            let standby = self
+                .tuned
+                .get(radio)
+                .copied()
+                .unwrap_or(radio.standby(&state.radios));
+            let displays = [
+                ("ACTIVE", radio.active(&state.radios)),
+                ("STANDBY", standby),
+            ];
+            for (display, frequency) in displays {
+                let channel = format!("{}_{display}", radio.name());
+                let value = radio.format(frequency);
+                if conn.supports(&channel) && self.outputs.update(&channel, &value, now) {
+                    conn.send(&channel, value);
+                }
+            }
+        }
+    }
+
+    /// Tune a standby frequency by the detents reported with `ENC:<RADIO>_WHOLE:<detents>` or `ENC:<RADIO>_FRACT:<detents>`.
+    fn handle_encoder(&mut self, msg: &str) {
+        let Some((name, detents)) = msg.split_once(':') else {
+            warn!("Malformed encoder message {msg:?}");
+            return;
+        };
+        let Some((radio, knob)) = name.split_once('_').and_then(|(radio, knob)| {
+            let radio = Radio::ALL.iter().find(|r| r.name() == radio)?;
+            Some((*radio, knob))
+        }) else {
+            debug!("Ignoring unknown encoder {name:?}");
+            return;
+        };
+        let Ok(detents) = detents.parse() else {
+            warn!("Invalid detents for encoder {name:?}: {detents:?}");
+            return;
+        };
+        let Some(state) = self.aircraft_sim_state.as_ref() else {
+            debug!("Ignoring encoder {name:?} before the simulator reported the frequencies");
+            return;
+        };
+
+        let standby = self
+            .tuned
+            .get(&radio)
+            .copied()
+            .unwrap_or(radio.standby(&state.radios));
+        let frequency = match knob {
+            "WHOLE" => radio.tune_whole(standby, detents),
+            "FRACT" => radio.tune_fraction(standby, detents, self.com_spacing),
+            _ => {
+                debug!("Ignoring unknown encoder {name:?}");
+                return;
+            }
+        };
+        self.tuned.insert(radio, frequency);
+        self.hw_tx
+            .send(Event::SetSimulator(radio.set_event(frequency)))
+            .expect("SimConnect thread offline");
+    }
+
+    fn handle_serial_command(&mut self, cmd: &str, capabilities: &Capabilities) {
+        debug!("Serial port received command: {:?}", cmd);
+        let Some((channel, pressed)) = cmd.split_once(':') else {
+            warn!("Malformed radio panel message {cmd:?}");
+            return;
+        };
+        if !capabilities.supports(channel) {
+            warn!("Radio panel sent command for unannounced channel {channel:?}");
+        }
+        let Some(radio) = Radio::ALL
+            .iter()
+            .find(|radio| channel == format!("{}_SWAP", radio.name()))
+        else {
+            return;
+        };
+
+        if pressed == "1" {
+            // The tuned frequency becomes active, so it must not be shown as standby any longer
+            self.tuned.remove(radio);
+            self.hw_tx
+                .send(Event::SetSimulator(radio.swap_event()))
+                .expect("SimConnect thread offline");
+        }
+    }
+}
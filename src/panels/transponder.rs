@@ -0,0 +1,205 @@
+use log::{debug, info, warn};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, OutputLimits, ResetConfig};
+use crate::output::OutputFilter;
+use crate::panel::{Capabilities, FirmwareVersion, Panel, PanelError};
+use crate::panels::line::{LineConnection, LineProtocol};
+use crate::sim::{AircraftSimState, SimClientEvent, TransponderMode};
+use crate::simvar::SimVar;
+use crate::Event;
+
+/// Serial protocol of the transponder panel.
+const PROTOCOL: LineProtocol = LineProtocol {
+    name: "transponder",
+    baud_rate: 115200,
+    firmware_versions: FirmwareVersion::new(1, 0, 0)..FirmwareVersion::new(2, 0, 0),
+    required_channels: &["KEY", "CODE_DISPLAY"],
+    // The panel was released after the capability discovery, so there is no legacy firmware
+    legacy_channels: &[],
+};
+
+/// Number of digits of a squawk code.
+const CODE_DIGITS: usize = 4;
+
+/// Time after the last key press after which an incomplete code entry is cancelled.
+const ENTRY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Squawk code that is being typed on the keypad.
+#[derive(Debug)]
+struct Entry {
+    digits: String,
+    last_key: Instant,
+}
+
+/// Represents the transponder panel with its keypad, IDENT button, mode knob and code display.
+///
+/// The keypad reports `KEY:<digit>` and `KEY:CLR`, the mode knob `MODE:<label>` and the IDENT button `IDENT:1`.
+#[derive(Debug)]
+pub struct TransponderPanel {
+    port: String,
+    reset: ResetConfig,
+    hw_tx: mpsc::Sender<Event>,
+    sim_rx: mpsc::Receiver<Event>,
+    aircraft_sim_state: Option<AircraftSimState>,
+    entry: Option<Entry>,
+    outputs: OutputFilter,
+}
+
+impl Panel for TransponderPanel {
+    /// Connect to the panel and run an event loop.
+    fn run(&mut self) -> Result<(), PanelError> {
+        let mut conn = LineConnection::open(&self.port, self.reset, &PROTOCOL)?;
+
+        loop {
+            // Receive control messages
+            match self.sim_rx.try_recv() {
+                Ok(Event::SetPanel(state)) => {
+                    self.aircraft_sim_state = Some(state);
+                }
+                Ok(Event::SimConnected) => {
+                    // Write all outputs again once the simulator sends its state
+                    self.aircraft_sim_state = None;
+                    self.outputs.clear();
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    // The simconnect thread cannot exit, we exit always first
+                    unreachable!();
+                }
+                _ => {}
+            }
+
+            // Read messages from serial port
+            if let Some(msg) = conn.poll()? {
+                self.handle_serial_command(&msg, &conn.capabilities);
+            }
+
+            // Give up on a code the pilot stopped typing
+            if self
+                .entry
+                .as_ref()
+                .is_some_and(|entry| entry.last_key.elapsed() > ENTRY_TIMEOUT)
+            {
+                info!("Squawk code entry timed out");
+                self.entry = None;
+            }
+
+            self.update_display(&mut conn);
+        }
+    }
+
+    fn probe(&mut self) -> Result<Capabilities, PanelError> {
+        let conn = LineConnection::open(&self.port, self.reset, &PROTOCOL)?;
+        Ok(conn.capabilities)
+    }
+
+    fn sim_vars(&self) -> Vec<SimVar> {
+        vec![SimVar::Transponder]
+    }
+}
+
+impl TransponderPanel {
+    /// Create a new panel instance.
+    pub fn new(
+        port: impl AsRef<str>,
+        config: &Config,
+        hw_tx: mpsc::Sender<Event>,
+        sim_rx: mpsc::Receiver<Event>,
+    ) -> Self {
+        Self {
+            port: port.as_ref().into(),
+            reset: config.panel_reset("transponder"),
+            hw_tx,
+            sim_rx,
+            aircraft_sim_state: None,
+            entry: None,
+            outputs: OutputFilter::from_config(config, "transponder", OutputLimits::default()),
+        }
+    }
+
+    /// Write the code, mode and IDENT state that are due according to the output limits.
+    ///
+    /// While a code is being typed, the display shows the entered digits followed by dashes.
+    fn update_display(&mut self, conn: &mut LineConnection) {
+        let code = match (&self.entry, &self.aircraft_sim_state) {
+            (Some(entry), _) => format!("{:-<width$}", entry.digits, width = CODE_DIGITS),
+            (None, Some(state)) => format!("{:04}", state.transponder.code),
+            (None, None) => return,
+        };
+        let mut outputs = vec![("CODE_DISPLAY", code)];
+        if let Some(state) = &self.aircraft_sim_state {
+            outputs.push(("MODE_DISPLAY", state.transponder.mode.label().into()));
+            outputs.push(("IDENT_LED", (state.transponder.ident as i32).to_string()));
+        }
+
+        let now = Instant::now();
+        for (channel, value) in outputs {
+            if conn.supports(channel) && self.outputs.update(channel, &value, now) {
+                conn.send(channel, value);
+            }
+        }
+    }
+
+    /// Add a digit typed on the keypad to the code and send it once it is complete.
+    fn handle_key(&mut self, key: &str) {
+        if key == "CLR" {
+            self.entry = None;
+            return;
+        }
+        // Squawk codes are octal, so the keypad has no 8 and 9
+        if !matches!(key, "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7") {
+            warn!("Invalid transponder key {key:?}");
+            return;
+        }
+
+        let entry = self.entry.get_or_insert_with(|| Entry {
+            digits: String::new(),
+            last_key: Instant::now(),
+        });
+        entry.digits.push_str(key);
+        entry.last_key = Instant::now();
+        if entry.digits.len() < CODE_DIGITS {
+            return;
+        }
+
+        let code = entry.digits.parse().expect("squawk code of digits");
+        info!("Setting squawk code {:04}", code);
+        self.entry = None;
+        self.hw_tx
+            .send(Event::SetSimulator(SimClientEvent::TransponderSet(code)))
+            .expect("SimConnect thread offline");
+    }
+
+    fn handle_serial_command(&mut self, cmd: &str, capabilities: &Capabilities) {
+        debug!("Serial port received command: {:?}", cmd);
+        let Some((channel, value)) = cmd.split_once(':') else {
+            warn!("Malformed transponder panel message {cmd:?}");
+            return;
+        };
+        if !capabilities.supports(channel) {
+            warn!("Transponder panel sent command for unannounced channel {channel:?}");
+        }
+
+        let event = match (channel, value) {
+            ("KEY", key) => return self.handle_key(key),
+            ("IDENT", "1") => SimClientEvent::TransponderIdent,
+            ("MODE", label) => {
+                match TransponderMode::ALL
+                    .iter()
+                    .find(|mode| mode.label() == label)
+                {
+                    Some(mode) => SimClientEvent::TransponderModeSet(*mode),
+                    None => {
+                        warn!("Unknown transponder mode {label:?}");
+                        return;
+                    }
+                }
+            }
+            _ => return,
+        };
+        self.hw_tx
+            .send(Event::SetSimulator(event))
+            .expect("SimConnect thread offline");
+    }
+}
@@ -0,0 +1,265 @@
+use log::warn;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::sync::{Arc, Mutex};
+
+use crate::config::{AircraftProfile, Config, GaugeConfig};
+use crate::sim::{AircraftSimState, SimClientEvent};
+
+/// Prefixes of profile events that are no simulator events, with the reason they are ignored.
+const UNSUPPORTED_EVENTS: &[(&str, &str)] = &[
+    // SimConnect input events need EnumerateInputEvents and SetInputEvent, which the SDK does not offer
+    ("B:", "input events are not supported"),
+    ("L:", "L:vars are written through MobiFlight presets"),
+    ("H:", "H:events are fired through MobiFlight presets"),
+    ("CALC:", "calculator code is run through MobiFlight presets"),
+];
+
+/// Aircraft profile that was selected for the loaded aircraft.
+#[derive(Debug)]
+pub struct Profile {
+    pub name: String,
+    pub config: AircraftProfile,
+    /// Simulator events by EventSim panel command.
+    pub events: HashMap<String, SimClientEvent>,
+}
+
+impl Profile {
+    /// Gauge that replaces the configured gauge of the panel with the given target.
+    pub fn gauge(&self, panel: &str, target: &str) -> Option<&GaugeConfig> {
+        self.config.gauges.get(panel)?.get(target)
+    }
+
+    /// Value of the L:var the profile reads for the channel.
+    pub fn lvar(&self, channel: &str, state: &AircraftSimState) -> Option<f64> {
+        let name = self.config.lvars.get(channel)?;
+        state.lvars.get(name).copied()
+    }
+}
+
+/// Selects the aircraft profile of the configuration that matches the loaded aircraft.
+#[derive(Debug)]
+pub struct ProfileSelector {
+    profiles: Vec<Arc<Profile>>,
+    /// Names of the simulator events used by the profiles, in the order of their client event IDs.
+    event_names: Vec<&'static CStr>,
+    /// L:vars read by any of the profiles.
+    lvars: Vec<String>,
+}
+
+impl ProfileSelector {
+    /// Selector of the profiles in the configuration, which are tried in alphabetical order.
+    pub fn new(config: &Config) -> Self {
+        let mut event_names: Vec<&'static CStr> = Vec::new();
+        let mut profiles = Vec::new();
+        let mut lvars: Vec<String> = Vec::new();
+        for (name, profile) in config.profiles() {
+            lvars.extend(profile.lvars.values().cloned());
+            let mut events = HashMap::new();
+            for (cmd, event) in &profile.events {
+                let (event_name, data) = match parse_event(event) {
+                    Ok(event) => event,
+                    Err(reason) => {
+                        warn!(
+                            "Ignoring event {event:?} for command {cmd:?} of profile '{name}': {reason}"
+                        );
+                        continue;
+                    }
+                };
+
+                // Every simulator event is mapped once, no matter how many commands send it
+                let index = match event_names.iter().position(|known| **known == *event_name) {
+                    Some(index) => index,
+                    None => {
+                        event_names.push(intern(event_name));
+                        event_names.len() - 1
+                    }
+                };
+                let event = SimClientEvent::Named {
+                    id: (SimClientEvent::ALL.len() + index) as u32,
+                    name: event_names[index],
+                    data,
+                };
+                events.insert(cmd.clone(), event);
+            }
+            profiles.push(Arc::new(Profile {
+                name,
+                config: profile,
+                events,
+            }));
+        }
+        lvars.sort();
+        lvars.dedup();
+        Self {
+            profiles,
+            event_names,
+            lvars,
+        }
+    }
+
+    /// Simulator events used by the profiles, which are mapped in addition to `SimClientEvent::ALL`.
+    pub fn events(&self) -> impl Iterator<Item = SimClientEvent> + '_ {
+        self.event_names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| SimClientEvent::Named {
+                id: (SimClientEvent::ALL.len() + index) as u32,
+                name,
+                data: 0,
+            })
+    }
+
+    /// L:vars read by any of the profiles, which are requested from the simulator together.
+    pub fn lvars(&self) -> &[String] {
+        &self.lvars
+    }
+
+    /// First profile matching the `TITLE` or `ATC MODEL` of the loaded aircraft.
+    pub fn select(&self, title: &str, atc_model: &str) -> Option<Arc<Profile>> {
+        let title = title.to_lowercase();
+        let atc_model = atc_model.to_lowercase();
+        self.profiles
+            .iter()
+            .find(|profile| {
+                profile.config.matches.iter().any(|pattern| {
+                    let pattern = pattern.to_lowercase();
+                    title.contains(&pattern) || atc_model.contains(&pattern)
+                })
+            })
+            .cloned()
+    }
+}
+
+/// Name and data of a profile event given as `<EVENT>` or `<EVENT>:<data>`.
+fn parse_event(event: &str) -> Result<(CString, u32), &'static str> {
+    if let Some((_, reason)) = UNSUPPORTED_EVENTS
+        .iter()
+        .find(|(prefix, _)| event.starts_with(prefix))
+    {
+        return Err(reason);
+    }
+    let (name, data) = event.split_once(':').unwrap_or((event, "0"));
+    let data = data.parse().map_err(|_| "the data is no number")?;
+    let name = CString::new(name).map_err(|_| "the name contains a zero byte")?;
+    Ok((name, data))
+}
+
+/// Simulator event name that lives until we exit, as the simulator connection may use it as long.
+///
+/// Every name is leaked only once, so selectors that are created again share the names.
+fn intern(name: CString) -> &'static CStr {
+    static NAMES: Mutex<Vec<&'static CStr>> = Mutex::new(Vec::new());
+    let mut names = NAMES.lock().unwrap();
+    if let Some(known) = names
+        .iter()
+        .copied()
+        .find(|known| *known == name.as_c_str())
+    {
+        return known;
+    }
+    let name = Box::leak(name.into_boxed_c_str());
+    names.push(name);
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        log_level = "info"
+
+        [panels]
+
+        [profiles.a320]
+        match = ["A320"]
+
+        [profiles.a320.events]
+        "MISC1:1" = "TOGGLE_TAXI_LIGHTS"
+        "MISC1:0" = "TOGGLE_TAXI_LIGHTS"
+        "MISC2:1" = "LOGO_LIGHTS_SET:1"
+        "MISC3:1" = "B:LIGHTING_LANDING_1:1"
+        "MISC4:1" = "L:LIGHTING_STROBE_0:2"
+
+        [profiles.c172]
+        match = ["Cessna 172", "C172"]
+    "#;
+
+    fn selector() -> ProfileSelector {
+        ProfileSelector::new(&toml::from_str(CONFIG).unwrap())
+    }
+
+    #[test]
+    fn parse_event_with_and_without_data() {
+        assert_eq!(
+            parse_event("TOGGLE_TAXI_LIGHTS"),
+            Ok((c"TOGGLE_TAXI_LIGHTS".into(), 0))
+        );
+        assert_eq!(
+            parse_event("LOGO_LIGHTS_SET:1"),
+            Ok((c"LOGO_LIGHTS_SET".into(), 1))
+        );
+        assert_eq!(
+            parse_event("MobiFlight.A320_LANDING_LIGHTS_ON"),
+            Ok((c"MobiFlight.A320_LANDING_LIGHTS_ON".into(), 0))
+        );
+        assert!(parse_event("LOGO_LIGHTS_SET:on").is_err());
+    }
+
+    #[test]
+    fn parse_event_rejects_other_variables() {
+        for event in [
+            "B:LIGHTING_LANDING_1",
+            "B:LIGHTING_LANDING_1:1",
+            "L:LIGHTING_TAXI_1",
+            "L:LIGHTING_TAXI_1:1",
+            "H:A320_Neo_CDU_1_BTN_INIT",
+            "CALC:1 (>K:TOGGLE_NAV_LIGHTS)",
+        ] {
+            assert!(parse_event(event).is_err(), "{event}");
+        }
+    }
+
+    #[test]
+    fn selector_maps_every_event_once() {
+        let selector = selector();
+        let profile = selector.select("Airbus A320 Neo", "").unwrap();
+
+        let SimClientEvent::Named { id, name, data } = profile.events["MISC2:1"] else {
+            panic!("profile event is no named event");
+        };
+        assert_eq!((name, data), (c"LOGO_LIGHTS_SET", 1));
+        assert!(!profile.events.contains_key("MISC3:1"));
+        assert!(!profile.events.contains_key("MISC4:1"));
+
+        let mut names = selector.event_names.clone();
+        assert_eq!(
+            selector.event_names[id as usize - SimClientEvent::ALL.len()],
+            name
+        );
+        names.sort();
+        assert_eq!(names, [c"LOGO_LIGHTS_SET", c"TOGGLE_TAXI_LIGHTS"]);
+    }
+
+    #[test]
+    fn selector_interns_event_names() {
+        let first = selector();
+        let second = selector();
+
+        for name in &first.event_names {
+            let other = second.event_names.iter().find(|other| *other == name);
+            assert!(other.is_some_and(|other| std::ptr::eq(*other, *name)));
+        }
+    }
+
+    #[test]
+    fn select_matches_title_or_atc_model_ignoring_case() {
+        let selector = selector();
+
+        let profile = selector.select("Cessna Skyhawk", "c172").unwrap();
+        assert_eq!(profile.name, "c172");
+        let profile = selector.select("CESSNA 172 SKYHAWK ASOBO", "").unwrap();
+        assert_eq!(profile.name, "c172");
+        assert!(selector.select("Boeing 747-8", "B748").is_none());
+    }
+}
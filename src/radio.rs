@@ -0,0 +1,129 @@
+use std::ops::RangeInclusive;
+
+use crate::config::ChannelSpacing;
+use crate::sim::{Frequency, RadioFrequencies, SimClientEvent};
+
+/// Channels within every 100 kHz of a COM radio with 25 kHz spacing, in kHz.
+const COM_CHANNELS_25KHZ: &[u32] = &[0, 25, 50, 75];
+
+/// Channel names within every 100 kHz of a COM radio with 8.33 kHz spacing, in kHz.
+///
+/// The names do not match the frequencies, for example 118.005 is the 8.33 kHz channel on 118.000 MHz, which is also
+/// reachable as the 25 kHz channel 118.000.
+const COM_CHANNELS_833KHZ: &[u32] = &[0, 5, 10, 15, 25, 30, 35, 40, 50, 55, 60, 65, 75, 80, 85, 90];
+
+/// Channels within every 100 kHz of a NAV radio, in kHz.
+const NAV_CHANNELS: &[u32] = &[0, 50];
+
+/// Radio of the radio stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Radio {
+    Com1,
+    Com2,
+    Nav1,
+    Nav2,
+}
+
+impl Radio {
+    pub const ALL: &'static [Radio] = &[Radio::Com1, Radio::Com2, Radio::Nav1, Radio::Nav2];
+
+    /// Name of the radio used as prefix of its panel channels.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Radio::Com1 => "COM1",
+            Radio::Com2 => "COM2",
+            Radio::Nav1 => "NAV1",
+            Radio::Nav2 => "NAV2",
+        }
+    }
+
+    pub fn active(&self, radios: &RadioFrequencies) -> Frequency {
+        match self {
+            Radio::Com1 => radios.com1_active,
+            Radio::Com2 => radios.com2_active,
+            Radio::Nav1 => radios.nav1_active,
+            Radio::Nav2 => radios.nav2_active,
+        }
+    }
+
+    pub fn standby(&self, radios: &RadioFrequencies) -> Frequency {
+        match self {
+            Radio::Com1 => radios.com1_standby,
+            Radio::Com2 => radios.com2_standby,
+            Radio::Nav1 => radios.nav1_standby,
+            Radio::Nav2 => radios.nav2_standby,
+        }
+    }
+
+    /// Event that exchanges the active and standby frequency.
+    pub fn swap_event(&self) -> SimClientEvent {
+        match self {
+            Radio::Com1 => SimClientEvent::Com1StandbySwap,
+            Radio::Com2 => SimClientEvent::Com2StandbySwap,
+            Radio::Nav1 => SimClientEvent::Nav1StandbySwap,
+            Radio::Nav2 => SimClientEvent::Nav2StandbySwap,
+        }
+    }
+
+    /// Event that tunes the standby frequency.
+    pub fn set_event(&self, frequency: Frequency) -> SimClientEvent {
+        match self {
+            Radio::Com1 => SimClientEvent::Com1StandbySet(frequency),
+            Radio::Com2 => SimClientEvent::Com2StandbySet(frequency),
+            Radio::Nav1 => SimClientEvent::Nav1StandbySet(frequency),
+            Radio::Nav2 => SimClientEvent::Nav2StandbySet(frequency),
+        }
+    }
+
+    /// Frequency in MHz as shown on the radio, with three decimals for COM and two for NAV radios.
+    pub fn format(&self, frequency: Frequency) -> String {
+        let khz = frequency.khz();
+        match self {
+            Radio::Com1 | Radio::Com2 => format!("{}.{:03}", khz / 1000, khz % 1000),
+            Radio::Nav1 | Radio::Nav2 => format!("{}.{:02}", khz / 1000, khz % 1000 / 10),
+        }
+    }
+
+    /// Tune the frequency by whole MHz, wrapping around at the ends of the band.
+    pub fn tune_whole(&self, frequency: Frequency, steps: i32) -> Frequency {
+        let band = self.band();
+        let mhz = (frequency.khz() / 1000).clamp(*band.start(), *band.end());
+        let count = (band.end() - band.start() + 1) as i32;
+        let mhz = band.start() + ((mhz - band.start()) as i32 + steps).rem_euclid(count) as u32;
+        Frequency::from_khz(mhz * 1000 + frequency.khz() % 1000)
+    }
+
+    /// Tune the frequency by channels within the MHz, wrapping around like the fraction knob of a real radio.
+    pub fn tune_fraction(
+        &self,
+        frequency: Frequency,
+        steps: i32,
+        spacing: ChannelSpacing,
+    ) -> Frequency {
+        let offsets = match (self, spacing) {
+            (Radio::Nav1 | Radio::Nav2, _) => NAV_CHANNELS,
+            (_, ChannelSpacing::Khz25) => COM_CHANNELS_25KHZ,
+            (_, ChannelSpacing::Khz833) => COM_CHANNELS_833KHZ,
+        };
+        let channels: Vec<u32> = (0..10)
+            .flat_map(|block| offsets.iter().map(move |offset| block * 100 + offset))
+            .collect();
+
+        // Frequencies between channels are tuned from the channel below them
+        let khz = frequency.khz();
+        let index = channels
+            .iter()
+            .rposition(|channel| *channel <= khz % 1000)
+            .unwrap_or(0);
+        let index = (index as i32 + steps).rem_euclid(channels.len() as i32) as usize;
+        Frequency::from_khz(khz / 1000 * 1000 + channels[index])
+    }
+
+    /// Whole MHz the radio can be tuned to.
+    fn band(&self) -> RangeInclusive<u32> {
+        match self {
+            Radio::Com1 | Radio::Com2 => 118..=136,
+            Radio::Nav1 | Radio::Nav2 => 108..=117,
+        }
+    }
+}
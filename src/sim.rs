@@ -1,14 +1,79 @@
-use std::{sync::mpsc, time::Duration};
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    fmt, mem,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
 
 use log::{debug, error, info, warn};
-use simconnect_sdk::{FlxClientEvent, Notification, SimConnect, SimConnectError, SimConnectObject};
+use simconnect_sdk::{
+    Condition, DataType, FlxClientEvent, Notification, Object, Period, SimConnect, SimConnectError,
+    SimConnectObject,
+};
 
+use crate::profile::ProfileSelector;
+use crate::simvar::Subscriber;
 use crate::Event;
 
 const SIMCONNECT_NAME: &str = "FSSK Panels";
 
+/// Request ID of the data definition with the L:vars of the aircraft profiles, far above the IDs that
+/// `register_object` assigns.
+const LVARS_REQUEST_ID: u32 = 1000;
+
+/// Names of the SimConnect exceptions in the order of their `SIMCONNECT_EXCEPTION` codes.
+const EXCEPTION_NAMES: &[&str] = &[
+    "NONE",
+    "ERROR",
+    "SIZE_MISMATCH",
+    "UNRECOGNIZED_ID",
+    "UNOPENED",
+    "VERSION_MISMATCH",
+    "TOO_MANY_GROUPS",
+    "NAME_UNRECOGNIZED",
+    "TOO_MANY_EVENT_NAMES",
+    "EVENT_ID_DUPLICATE",
+    "TOO_MANY_MAPS",
+    "TOO_MANY_OBJECTS",
+    "TOO_MANY_REQUESTS",
+    "WEATHER_INVALID_PORT",
+    "WEATHER_INVALID_METAR",
+    "WEATHER_UNABLE_TO_GET_OBSERVATION",
+    "WEATHER_UNABLE_TO_CREATE_STATION",
+    "WEATHER_UNABLE_TO_REMOVE_STATION",
+    "INVALID_DATA_TYPE",
+    "INVALID_DATA_SIZE",
+    "DATA_ERROR",
+    "INVALID_ARRAY",
+    "CREATE_OBJECT_FAILED",
+    "LOAD_FLIGHTPLAN_FAILED",
+    "OPERATION_INVALID_FOR_OBJECT_TYPE",
+    "ILLEGAL_OPERATION",
+    "ALREADY_SUBSCRIBED",
+    "INVALID_ENUM",
+    "DEFINITION_ERROR",
+    "DUPLICATE_ID",
+    "DATUM_ID",
+    "OUT_OF_BOUNDS",
+    "ALREADY_CREATED",
+    "OBJECT_OUTSIDE_REALITY_BUBBLE",
+    "OBJECT_CONTAINER",
+    "OBJECT_AI",
+    "OBJECT_ATC",
+    "OBJECT_SCHEDULE",
+];
+
+/// Exceptions after which the connection cannot be used any longer.
+const FATAL_EXCEPTIONS: &[&str] = &["UNOPENED", "VERSION_MISMATCH"];
+
 /// A data structure that will be used to receive data from SimConnect.
 /// See the documentation of `SimConnectObject` for more information on the arguments of the `simconnect` attribute.
+///
+/// Every field is read as a 64-bit float and converted in `AircraftSimState::from`, including booleans with the unit
+/// `bool`. The `simconnect-sdk` crate does not read a boolean field with the size SimConnect sends it in, which shifts
+/// every field that follows and corrupts e.g. the gear positions. Strings go into a data definition of their own like
+/// `AircraftIdentity` for the same reason. The size of the struct is checked against `AIRCRAFT_SIM_DATA_FIELDS`.
 #[derive(Debug, Clone, SimConnectObject)]
 #[simconnect(period = "sim-frame", condition = "changed")]
 struct AircraftSimData {
@@ -20,40 +85,386 @@ struct AircraftSimData {
     gear_right_position: f64,
     #[simconnect(name = "AIRSPEED INDICATED", unit = "knots")]
     airspeed: f64,
+    #[simconnect(name = "INDICATED ALTITUDE", unit = "feet")]
+    indicated_altitude: f64,
+    #[simconnect(name = "VERTICAL SPEED", unit = "feet/minute")]
+    vertical_speed: f64,
+    #[simconnect(name = "HEADING INDICATOR", unit = "degrees")]
+    heading_indicator: f64,
+    #[simconnect(name = "TURN INDICATOR RATE", unit = "degrees per second")]
+    turn_rate: f64,
+    #[simconnect(name = "TURN COORDINATOR BALL", unit = "position")]
+    turn_coordinator_ball: f64,
+    #[simconnect(name = "ATTITUDE INDICATOR PITCH DEGREES", unit = "degrees")]
+    attitude_pitch: f64,
+    #[simconnect(name = "ATTITUDE INDICATOR BANK DEGREES", unit = "degrees")]
+    attitude_bank: f64,
+    #[simconnect(name = "PLANE ALT ABOVE GROUND", unit = "feet")]
+    altitude_above_ground: f64,
+    #[simconnect(name = "SIM ON GROUND", unit = "bool")]
+    on_ground: f64,
+    #[simconnect(name = "AUTOPILOT HEADING LOCK DIR", unit = "degrees")]
+    heading_bug: f64,
+    #[simconnect(name = "AUTOPILOT ALTITUDE LOCK VAR", unit = "feet")]
+    autopilot_altitude: f64,
+    #[simconnect(name = "AUTOPILOT VERTICAL HOLD VAR", unit = "feet/minute")]
+    autopilot_vertical_speed: f64,
+    #[simconnect(name = "AUTOPILOT AIRSPEED HOLD VAR", unit = "knots")]
+    autopilot_airspeed: f64,
+    #[simconnect(name = "AUTOPILOT MASTER", unit = "bool")]
+    autopilot_master: f64,
+    #[simconnect(name = "AUTOPILOT HEADING LOCK", unit = "bool")]
+    autopilot_heading_lock: f64,
+    #[simconnect(name = "AUTOPILOT ALTITUDE LOCK", unit = "bool")]
+    autopilot_altitude_lock: f64,
+    #[simconnect(name = "AUTOPILOT VERTICAL HOLD", unit = "bool")]
+    autopilot_vertical_hold: f64,
+    #[simconnect(name = "AUTOPILOT AIRSPEED HOLD", unit = "bool")]
+    autopilot_airspeed_hold: f64,
+    #[simconnect(name = "AUTOPILOT NAV1 LOCK", unit = "bool")]
+    autopilot_nav1_lock: f64,
+    #[simconnect(name = "AUTOPILOT APPROACH HOLD", unit = "bool")]
+    autopilot_approach_hold: f64,
+    #[simconnect(name = "COM ACTIVE FREQUENCY:1", unit = "Hz")]
+    com1_active_frequency: f64,
+    #[simconnect(name = "COM STANDBY FREQUENCY:1", unit = "Hz")]
+    com1_standby_frequency: f64,
+    #[simconnect(name = "COM ACTIVE FREQUENCY:2", unit = "Hz")]
+    com2_active_frequency: f64,
+    #[simconnect(name = "COM STANDBY FREQUENCY:2", unit = "Hz")]
+    com2_standby_frequency: f64,
+    #[simconnect(name = "NAV ACTIVE FREQUENCY:1", unit = "Frequency BCD16")]
+    nav1_active_frequency: f64,
+    #[simconnect(name = "NAV STANDBY FREQUENCY:1", unit = "Frequency BCD16")]
+    nav1_standby_frequency: f64,
+    #[simconnect(name = "NAV ACTIVE FREQUENCY:2", unit = "Frequency BCD16")]
+    nav2_active_frequency: f64,
+    #[simconnect(name = "NAV STANDBY FREQUENCY:2", unit = "Frequency BCD16")]
+    nav2_standby_frequency: f64,
+    #[simconnect(name = "TRANSPONDER CODE:1", unit = "Bco16")]
+    transponder_code: f64,
+    #[simconnect(name = "TRANSPONDER STATE:1", unit = "Enum")]
+    transponder_state: f64,
+    #[simconnect(name = "TRANSPONDER IDENT:1", unit = "bool")]
+    transponder_ident: f64,
+    #[simconnect(name = "KOHLSMAN SETTING HG", unit = "inHg")]
+    kohlsman_setting: f64,
+    #[simconnect(name = "FLAPS HANDLE INDEX", unit = "number")]
+    flaps_handle_index: f64,
+    #[simconnect(name = "FLAPS NUM HANDLE POSITIONS", unit = "number")]
+    flaps_num_handle_positions: f64,
+    #[simconnect(name = "IS GEAR RETRACTABLE", unit = "bool")]
+    gear_retractable: f64,
+    #[simconnect(name = "GEAR HANDLE POSITION", unit = "bool")]
+    gear_handle_position: f64,
+    #[simconnect(name = "BRAKE PARKING INDICATOR", unit = "bool")]
+    parking_brake_indicator: f64,
+    #[simconnect(name = "LIGHT LANDING", unit = "bool")]
+    light_landing: f64,
+    #[simconnect(name = "LIGHT TAXI", unit = "bool")]
+    light_taxi: f64,
+    #[simconnect(name = "LIGHT STROBE", unit = "bool")]
+    light_strobe: f64,
+    #[simconnect(name = "LIGHT NAV", unit = "bool")]
+    light_nav: f64,
+    #[simconnect(name = "LIGHT BEACON", unit = "bool")]
+    light_beacon: f64,
+    #[simconnect(name = "LIGHT WING", unit = "bool")]
+    light_wing: f64,
+    #[simconnect(name = "LIGHT LOGO", unit = "bool")]
+    light_logo: f64,
+    #[simconnect(name = "LIGHT RECOGNITION", unit = "bool")]
+    light_recognition: f64,
+    #[simconnect(name = "LIGHT PANEL", unit = "bool")]
+    light_panel: f64,
+    #[simconnect(name = "LIGHT CABIN", unit = "bool")]
+    light_cabin: f64,
+}
 
-    /// Parking brake indicator.
-    ///
-    /// WARNING: Must be the last entry in the struct due to a bug in the `simconnect-sdk` crate, otherwise the gear
-    /// position values are interpreted incorrectly.
-    #[simconnect(name = "BRAKE PARKING INDICATOR")]
-    parking_brake_indicator: bool,
+/// Number of fields of `AircraftSimData`, which goes up with every SimVar that is added.
+const AIRCRAFT_SIM_DATA_FIELDS: usize = 51;
+
+// A field that is not an `f64` changes the size of the struct and breaks the build instead of shifting the fields
+const _: () = assert!(
+    std::mem::size_of::<AircraftSimData>() == AIRCRAFT_SIM_DATA_FIELDS * std::mem::size_of::<f64>()
+);
+
+/// Identification of the loaded aircraft, which selects the aircraft profile.
+///
+/// The strings are requested in a data definition of their own, so that they do not shift the fields of
+/// `AircraftSimData`.
+#[derive(Debug, Clone, PartialEq, SimConnectObject)]
+#[simconnect(period = "second", condition = "changed")]
+struct AircraftIdentity {
+    #[simconnect(name = "TITLE")]
+    title: String,
+    #[simconnect(name = "ATC MODEL")]
+    atc_model: String,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct AircraftSimState {
     pub parking_brake_indicator: bool,
     pub gear_center_state: LandingGearStatus,
     pub gear_left_state: LandingGearStatus,
     pub gear_right_state: LandingGearStatus,
+    /// The aircraft can retract its gear, fixed gear is always reported as down.
+    pub gear_retractable: bool,
+    /// The gear handle in the cockpit is in the down position.
+    pub gear_handle_down: bool,
     pub airspeed: f64,
+    pub altitude_above_ground: f64,
+    pub on_ground: bool,
+    /// Altitude shown by the altimeter in feet.
+    pub indicated_altitude: f64,
+    /// Vertical speed in feet per minute.
+    pub vertical_speed: f64,
+    /// Heading shown by the heading indicator in degrees.
+    pub heading_indicator: f64,
+    /// Turn rate in degrees per second.
+    pub turn_rate: f64,
+    /// Position of the slip ball from -127 (left) to 127 (right).
+    pub turn_coordinator_ball: f64,
+    /// Pitch shown by the attitude indicator in degrees.
+    pub attitude_pitch: f64,
+    /// Bank shown by the attitude indicator in degrees.
+    pub attitude_bank: f64,
+    /// Heading bug in degrees.
+    pub heading_bug: f64,
+    /// Altimeter setting in inches of mercury.
+    pub kohlsman_setting: f64,
+    pub lights: LightStates,
+    pub autopilot: AutopilotState,
+    pub radios: RadioFrequencies,
+    pub transponder: TransponderState,
+    /// Values of the L:vars read for the aircraft profiles by name, shared by the states sent to all panels.
+    pub lvars: Arc<HashMap<String, f64>>,
+    /// Detent of the flap handle, where 0 is fully retracted.
+    pub flaps_handle_index: u32,
+    /// Number of detents of the flap handle, excluding the retracted position.
+    pub flaps_handle_positions: u32,
+}
+
+/// Whether the individual lights of the aircraft are switched on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LightStates {
+    pub landing: bool,
+    pub taxi: bool,
+    pub strobe: bool,
+    pub nav: bool,
+    pub beacon: bool,
+    pub wing: bool,
+    pub logo: bool,
+    pub recognition: bool,
+    pub panel: bool,
+    pub cabin: bool,
+}
+
+/// Modes and targets of the autopilot, the heading target is the heading bug.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AutopilotState {
+    pub master: bool,
+    pub heading_lock: bool,
+    pub altitude_lock: bool,
+    pub vertical_hold: bool,
+    pub airspeed_hold: bool,
+    pub nav1_lock: bool,
+    pub approach_hold: bool,
+    /// Target altitude in feet.
+    pub altitude: f64,
+    /// Target vertical speed in feet per minute.
+    pub vertical_speed: f64,
+    /// Target airspeed in knots.
+    pub airspeed: f64,
+}
+
+/// Active and standby frequencies of the radio stack.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RadioFrequencies {
+    pub com1_active: Frequency,
+    pub com1_standby: Frequency,
+    pub com2_active: Frequency,
+    pub com2_standby: Frequency,
+    pub nav1_active: Frequency,
+    pub nav1_standby: Frequency,
+    pub nav2_active: Frequency,
+    pub nav2_standby: Frequency,
+}
+
+/// Radio frequency, which SimConnect represents either in Hz or as binary-coded decimal.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Frequency(u32);
+
+impl Frequency {
+    pub const fn from_hz(hz: u32) -> Self {
+        Self(hz)
+    }
+
+    pub const fn from_khz(khz: u32) -> Self {
+        Self(khz * 1000)
+    }
+
+    /// Decode a BCD16 frequency, which holds the four digits from 10 MHz to 10 kHz without the leading 1.
+    ///
+    /// For example `0x1350` is 113.50 MHz.
+    pub fn from_bcd16(bcd: u32) -> Self {
+        Self::from_khz(100_000 + bcd16_decode(bcd) * 10)
+    }
+
+    pub fn hz(&self) -> u32 {
+        self.0
+    }
+
+    pub fn khz(&self) -> u32 {
+        self.0 / 1000
+    }
+
+    /// Encode the frequency as BCD16, dropping the leading 1 and everything below 10 kHz.
+    pub fn bcd16(&self) -> u32 {
+        bcd16_encode(self.khz() / 10 % 10_000)
+    }
+}
+
+/// Decode four binary-coded decimal digits, for example `0x1234` is 1234.
+fn bcd16_decode(bcd: u32) -> u32 {
+    (0..4).rev().fold(0, |digits, nibble| {
+        digits * 10 + ((bcd >> (nibble * 4)) & 0xf)
+    })
+}
+
+/// Encode four decimal digits as binary-coded decimal, for example 1234 is `0x1234`.
+fn bcd16_encode(digits: u32) -> u32 {
+    (0..4).fold(0, |bcd, nibble| {
+        bcd | ((digits / 10u32.pow(nibble) % 10) << (nibble * 4))
+    })
+}
+
+/// Mode of the transponder as reported by `TRANSPONDER STATE`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TransponderMode {
+    #[default]
+    Off,
+    Standby,
+    Test,
+    On,
+    Altitude,
+    Ground,
+}
+
+impl TransponderMode {
+    pub const ALL: &'static [TransponderMode] = &[
+        TransponderMode::Off,
+        TransponderMode::Standby,
+        TransponderMode::Test,
+        TransponderMode::On,
+        TransponderMode::Altitude,
+        TransponderMode::Ground,
+    ];
+
+    /// Label of the mode as shown on the panel and reported by its mode knob.
+    pub fn label(&self) -> &'static str {
+        match self {
+            TransponderMode::Off => "OFF",
+            TransponderMode::Standby => "SBY",
+            TransponderMode::Test => "TST",
+            TransponderMode::On => "ON",
+            TransponderMode::Altitude => "ALT",
+            TransponderMode::Ground => "GND",
+        }
+    }
+}
+
+impl From<f64> for TransponderMode {
+    fn from(value: f64) -> Self {
+        TransponderMode::ALL
+            .get(value as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// State of the transponder.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TransponderState {
+    /// Squawk code with one decimal digit per octal digit, for example 7700.
+    pub code: u32,
+    pub mode: TransponderMode,
+    pub ident: bool,
 }
 
 impl From<AircraftSimData> for AircraftSimState {
     fn from(value: AircraftSimData) -> Self {
         Self {
-            parking_brake_indicator: value.parking_brake_indicator,
+            parking_brake_indicator: value.parking_brake_indicator != 0.0,
             gear_center_state: value.gear_center_position.into(),
             gear_left_state: value.gear_left_position.into(),
             gear_right_state: value.gear_right_position.into(),
+            gear_retractable: value.gear_retractable != 0.0,
+            gear_handle_down: value.gear_handle_position != 0.0,
             airspeed: value.airspeed,
+            altitude_above_ground: value.altitude_above_ground,
+            on_ground: value.on_ground != 0.0,
+            indicated_altitude: value.indicated_altitude,
+            vertical_speed: value.vertical_speed,
+            heading_indicator: value.heading_indicator,
+            turn_rate: value.turn_rate,
+            turn_coordinator_ball: value.turn_coordinator_ball,
+            attitude_pitch: value.attitude_pitch,
+            attitude_bank: value.attitude_bank,
+            heading_bug: value.heading_bug,
+            kohlsman_setting: value.kohlsman_setting,
+            flaps_handle_index: value.flaps_handle_index as u32,
+            flaps_handle_positions: value.flaps_num_handle_positions as u32,
+            lights: LightStates {
+                landing: value.light_landing != 0.0,
+                taxi: value.light_taxi != 0.0,
+                strobe: value.light_strobe != 0.0,
+                nav: value.light_nav != 0.0,
+                beacon: value.light_beacon != 0.0,
+                wing: value.light_wing != 0.0,
+                logo: value.light_logo != 0.0,
+                recognition: value.light_recognition != 0.0,
+                panel: value.light_panel != 0.0,
+                cabin: value.light_cabin != 0.0,
+            },
+            autopilot: AutopilotState {
+                master: value.autopilot_master != 0.0,
+                heading_lock: value.autopilot_heading_lock != 0.0,
+                altitude_lock: value.autopilot_altitude_lock != 0.0,
+                vertical_hold: value.autopilot_vertical_hold != 0.0,
+                airspeed_hold: value.autopilot_airspeed_hold != 0.0,
+                nav1_lock: value.autopilot_nav1_lock != 0.0,
+                approach_hold: value.autopilot_approach_hold != 0.0,
+                altitude: value.autopilot_altitude,
+                vertical_speed: value.autopilot_vertical_speed,
+                airspeed: value.autopilot_airspeed,
+            },
+            radios: RadioFrequencies {
+                com1_active: Frequency::from_hz(value.com1_active_frequency.round() as u32),
+                com1_standby: Frequency::from_hz(value.com1_standby_frequency.round() as u32),
+                com2_active: Frequency::from_hz(value.com2_active_frequency.round() as u32),
+                com2_standby: Frequency::from_hz(value.com2_standby_frequency.round() as u32),
+                nav1_active: Frequency::from_bcd16(value.nav1_active_frequency as u32),
+                nav1_standby: Frequency::from_bcd16(value.nav1_standby_frequency as u32),
+                nav2_active: Frequency::from_bcd16(value.nav2_active_frequency as u32),
+                nav2_standby: Frequency::from_bcd16(value.nav2_standby_frequency as u32),
+            },
+            transponder: TransponderState {
+                code: bcd16_decode(value.transponder_code as u32),
+                mode: value.transponder_state.into(),
+                ident: value.transponder_ident != 0.0,
+            },
+            lvars: Arc::default(),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum LandingGearStatus {
+    #[default]
     Unknown,
     Up,
+    InTransit,
     Down,
 }
 
@@ -63,24 +474,15 @@ impl From<f64> for LandingGearStatus {
             Self::Up
         } else if value == 1.0 {
             Self::Down
+        } else if value > 0.0 && value < 1.0 {
+            Self::InTransit
         } else {
             Self::Unknown
         }
     }
 }
 
-impl LandingGearStatus {
-    pub fn as_int(&self) -> i32 {
-        match self {
-            LandingGearStatus::Up => 0,
-            LandingGearStatus::Down => 1,
-            LandingGearStatus::Unknown => 2,
-        }
-    }
-}
-
 #[derive(Debug, Clone, Copy)]
-#[repr(u32)]
 pub enum SimClientEvent {
     LandingLightsOn,
     LandingLightsOff,
@@ -90,59 +492,358 @@ pub enum SimClientEvent {
     StrobeLightsOff,
     NavLightsOn,
     NavLightsOff,
+    BeaconLightsOn,
+    BeaconLightsOff,
+    WingLightsOn,
+    WingLightsOff,
+    LogoLightsOn,
+    LogoLightsOff,
+    RecognitionLightsOn,
+    RecognitionLightsOff,
+    PanelLightsOn,
+    PanelLightsOff,
+    CabinLightsOn,
+    CabinLightsOff,
     FlapsUp,
     FlapsDown,
+    /// Set the flap handle to a position between 0 (retracted) and 16383 (fully extended).
+    FlapsSet(u32),
+    FlapsRetract,
+    Flaps1,
+    Flaps2,
+    Flaps3,
+    FlapsExtend,
     ParkingBrakeOn,
     ParkingBrakeOff,
     LandingGearUp,
     LandingGearDown,
+    /// Set an axis to a value between -16383 and 16383.
+    ThrottleAxisSet(i32),
+    MixtureAxisSet(i32),
+    PropellerAxisSet(i32),
+    ElevatorTrimAxisSet(i32),
+    HeadingBugInc,
+    HeadingBugDec,
+    /// Set the heading bug in degrees.
+    HeadingBugSet(u32),
+    KohlsmanInc,
+    KohlsmanDec,
+    /// Set the altimeter in millibars times 16.
+    KohlsmanSet(u32),
+    ComRadioWholeInc,
+    ComRadioWholeDec,
+    ComRadioFractInc,
+    ComRadioFractDec,
+    // The autopilot mode events toggle the mode
+    AutopilotMaster,
+    AutopilotHeadingHold,
+    AutopilotAltitudeHold,
+    AutopilotVerticalSpeedHold,
+    AutopilotAirspeedHold,
+    AutopilotNavHold,
+    AutopilotApproachHold,
+    AutopilotAltitudeInc,
+    AutopilotAltitudeDec,
+    /// Set the autopilot target altitude in feet.
+    AutopilotAltitudeSet(u32),
+    AutopilotVerticalSpeedInc,
+    AutopilotVerticalSpeedDec,
+    /// Set the autopilot target vertical speed in feet per minute.
+    AutopilotVerticalSpeedSet(i32),
+    AutopilotAirspeedInc,
+    AutopilotAirspeedDec,
+    /// Set the autopilot target airspeed in knots.
+    AutopilotAirspeedSet(u32),
+    Com1StandbySwap,
+    Com2StandbySwap,
+    Nav1StandbySwap,
+    Nav2StandbySwap,
+    Com1StandbySet(Frequency),
+    Com2StandbySet(Frequency),
+    Nav1StandbySet(Frequency),
+    Nav2StandbySet(Frequency),
+    /// Set the squawk code, given with one decimal digit per octal digit.
+    TransponderSet(u32),
+    TransponderIdent,
+    TransponderModeSet(TransponderMode),
+    /// Simulator event named in an aircraft profile, with the client event ID it is mapped to.
+    Named {
+        id: u32,
+        name: &'static CStr,
+        data: u32,
+    },
 }
+
+impl SimClientEvent {
+    /// All events, which are mapped to their simulator events after connecting.
+    ///
+    /// The position of an event in this list is its client event ID, events carrying data are listed once.
+    pub const ALL: &'static [SimClientEvent] = &[
+        SimClientEvent::LandingLightsOn,
+        SimClientEvent::LandingLightsOff,
+        SimClientEvent::TaxiLightsOn,
+        SimClientEvent::TaxiLightsOff,
+        SimClientEvent::StrobeLightsOn,
+        SimClientEvent::StrobeLightsOff,
+        SimClientEvent::NavLightsOn,
+        SimClientEvent::NavLightsOff,
+        SimClientEvent::BeaconLightsOn,
+        SimClientEvent::BeaconLightsOff,
+        SimClientEvent::WingLightsOn,
+        SimClientEvent::WingLightsOff,
+        SimClientEvent::LogoLightsOn,
+        SimClientEvent::LogoLightsOff,
+        SimClientEvent::RecognitionLightsOn,
+        SimClientEvent::RecognitionLightsOff,
+        SimClientEvent::PanelLightsOn,
+        SimClientEvent::PanelLightsOff,
+        SimClientEvent::CabinLightsOn,
+        SimClientEvent::CabinLightsOff,
+        SimClientEvent::FlapsUp,
+        SimClientEvent::FlapsDown,
+        SimClientEvent::FlapsSet(0),
+        SimClientEvent::FlapsRetract,
+        SimClientEvent::Flaps1,
+        SimClientEvent::Flaps2,
+        SimClientEvent::Flaps3,
+        SimClientEvent::FlapsExtend,
+        SimClientEvent::ParkingBrakeOn,
+        SimClientEvent::ParkingBrakeOff,
+        SimClientEvent::LandingGearUp,
+        SimClientEvent::LandingGearDown,
+        SimClientEvent::ThrottleAxisSet(0),
+        SimClientEvent::MixtureAxisSet(0),
+        SimClientEvent::PropellerAxisSet(0),
+        SimClientEvent::ElevatorTrimAxisSet(0),
+        SimClientEvent::HeadingBugInc,
+        SimClientEvent::HeadingBugDec,
+        SimClientEvent::HeadingBugSet(0),
+        SimClientEvent::KohlsmanInc,
+        SimClientEvent::KohlsmanDec,
+        SimClientEvent::KohlsmanSet(0),
+        SimClientEvent::ComRadioWholeInc,
+        SimClientEvent::ComRadioWholeDec,
+        SimClientEvent::ComRadioFractInc,
+        SimClientEvent::ComRadioFractDec,
+        SimClientEvent::AutopilotMaster,
+        SimClientEvent::AutopilotHeadingHold,
+        SimClientEvent::AutopilotAltitudeHold,
+        SimClientEvent::AutopilotVerticalSpeedHold,
+        SimClientEvent::AutopilotAirspeedHold,
+        SimClientEvent::AutopilotNavHold,
+        SimClientEvent::AutopilotApproachHold,
+        SimClientEvent::AutopilotAltitudeInc,
+        SimClientEvent::AutopilotAltitudeDec,
+        SimClientEvent::AutopilotAltitudeSet(0),
+        SimClientEvent::AutopilotVerticalSpeedInc,
+        SimClientEvent::AutopilotVerticalSpeedDec,
+        SimClientEvent::AutopilotVerticalSpeedSet(0),
+        SimClientEvent::AutopilotAirspeedInc,
+        SimClientEvent::AutopilotAirspeedDec,
+        SimClientEvent::AutopilotAirspeedSet(0),
+        SimClientEvent::Com1StandbySwap,
+        SimClientEvent::Com2StandbySwap,
+        SimClientEvent::Nav1StandbySwap,
+        SimClientEvent::Nav2StandbySwap,
+        SimClientEvent::Com1StandbySet(Frequency::from_hz(0)),
+        SimClientEvent::Com2StandbySet(Frequency::from_hz(0)),
+        SimClientEvent::Nav1StandbySet(Frequency::from_hz(0)),
+        SimClientEvent::Nav2StandbySet(Frequency::from_hz(0)),
+        SimClientEvent::TransponderSet(0),
+        SimClientEvent::TransponderIdent,
+        SimClientEvent::TransponderModeSet(TransponderMode::Off),
+    ];
+
+    /// Name of the simulator event the client event is mapped to.
+    pub fn name(&self) -> &'static CStr {
+        match self {
+            SimClientEvent::LandingLightsOn => c"LANDING_LIGHTS_ON",
+            SimClientEvent::LandingLightsOff => c"LANDING_LIGHTS_OFF",
+            SimClientEvent::TaxiLightsOn => c"TAXI_LIGHTS_ON",
+            SimClientEvent::TaxiLightsOff => c"TAXI_LIGHTS_OFF",
+            SimClientEvent::StrobeLightsOn => c"STROBES_ON",
+            SimClientEvent::StrobeLightsOff => c"STROBES_OFF",
+            SimClientEvent::NavLightsOn => c"NAV_LIGHTS_ON",
+            SimClientEvent::NavLightsOff => c"NAV_LIGHTS_OFF",
+            SimClientEvent::BeaconLightsOn => c"BEACON_LIGHTS_ON",
+            SimClientEvent::BeaconLightsOff => c"BEACON_LIGHTS_OFF",
+            SimClientEvent::WingLightsOn => c"WING_LIGHTS_ON",
+            SimClientEvent::WingLightsOff => c"WING_LIGHTS_OFF",
+            SimClientEvent::LogoLightsOn => c"LOGO_LIGHTS_SET",
+            SimClientEvent::LogoLightsOff => c"LOGO_LIGHTS_SET",
+            SimClientEvent::RecognitionLightsOn => c"RECOGNITION_LIGHTS_SET",
+            SimClientEvent::RecognitionLightsOff => c"RECOGNITION_LIGHTS_SET",
+            SimClientEvent::PanelLightsOn => c"PANEL_LIGHTS_ON",
+            SimClientEvent::PanelLightsOff => c"PANEL_LIGHTS_OFF",
+            SimClientEvent::CabinLightsOn => c"CABIN_LIGHTS_ON",
+            SimClientEvent::CabinLightsOff => c"CABIN_LIGHTS_OFF",
+            SimClientEvent::FlapsUp => c"FLAPS_DECR",
+            SimClientEvent::FlapsDown => c"FLAPS_INCR",
+            SimClientEvent::FlapsSet(_) => c"FLAPS_SET",
+            SimClientEvent::FlapsRetract => c"FLAPS_UP",
+            SimClientEvent::Flaps1 => c"FLAPS_1",
+            SimClientEvent::Flaps2 => c"FLAPS_2",
+            SimClientEvent::Flaps3 => c"FLAPS_3",
+            SimClientEvent::FlapsExtend => c"FLAPS_DOWN",
+            SimClientEvent::ParkingBrakeOn => c"PARKING_BRAKE_SET",
+            SimClientEvent::ParkingBrakeOff => c"PARKING_BRAKE_SET",
+            SimClientEvent::LandingGearUp => c"GEAR_UP",
+            SimClientEvent::LandingGearDown => c"GEAR_DOWN",
+            SimClientEvent::ThrottleAxisSet(_) => c"AXIS_THROTTLE_SET",
+            SimClientEvent::MixtureAxisSet(_) => c"AXIS_MIXTURE_SET",
+            SimClientEvent::PropellerAxisSet(_) => c"AXIS_PROPELLER_SET",
+            SimClientEvent::ElevatorTrimAxisSet(_) => c"AXIS_ELEV_TRIM_SET",
+            SimClientEvent::HeadingBugInc => c"HEADING_BUG_INC",
+            SimClientEvent::HeadingBugDec => c"HEADING_BUG_DEC",
+            SimClientEvent::HeadingBugSet(_) => c"HEADING_BUG_SET",
+            SimClientEvent::KohlsmanInc => c"KOHLSMAN_INC",
+            SimClientEvent::KohlsmanDec => c"KOHLSMAN_DEC",
+            SimClientEvent::KohlsmanSet(_) => c"KOHLSMAN_SET",
+            SimClientEvent::ComRadioWholeInc => c"COM_RADIO_WHOLE_INC",
+            SimClientEvent::ComRadioWholeDec => c"COM_RADIO_WHOLE_DEC",
+            SimClientEvent::ComRadioFractInc => c"COM_RADIO_FRACT_INC",
+            SimClientEvent::ComRadioFractDec => c"COM_RADIO_FRACT_DEC",
+            SimClientEvent::AutopilotMaster => c"AP_MASTER",
+            SimClientEvent::AutopilotHeadingHold => c"AP_HDG_HOLD",
+            SimClientEvent::AutopilotAltitudeHold => c"AP_ALT_HOLD",
+            SimClientEvent::AutopilotVerticalSpeedHold => c"AP_VS_HOLD",
+            SimClientEvent::AutopilotAirspeedHold => c"AP_AIRSPEED_HOLD",
+            SimClientEvent::AutopilotNavHold => c"AP_NAV1_HOLD",
+            SimClientEvent::AutopilotApproachHold => c"AP_APR_HOLD",
+            SimClientEvent::AutopilotAltitudeInc => c"AP_ALT_VAR_INC",
+            SimClientEvent::AutopilotAltitudeDec => c"AP_ALT_VAR_DEC",
+            SimClientEvent::AutopilotAltitudeSet(_) => c"AP_ALT_VAR_SET_ENGLISH",
+            SimClientEvent::AutopilotVerticalSpeedInc => c"AP_VS_VAR_INC",
+            SimClientEvent::AutopilotVerticalSpeedDec => c"AP_VS_VAR_DEC",
+            SimClientEvent::AutopilotVerticalSpeedSet(_) => c"AP_VS_VAR_SET_ENGLISH",
+            SimClientEvent::AutopilotAirspeedInc => c"AP_SPD_VAR_INC",
+            SimClientEvent::AutopilotAirspeedDec => c"AP_SPD_VAR_DEC",
+            SimClientEvent::AutopilotAirspeedSet(_) => c"AP_SPD_VAR_SET",
+            SimClientEvent::Com1StandbySwap => c"COM_STBY_RADIO_SWAP",
+            SimClientEvent::Com2StandbySwap => c"COM2_RADIO_SWAP",
+            SimClientEvent::Nav1StandbySwap => c"NAV1_RADIO_SWAP",
+            SimClientEvent::Nav2StandbySwap => c"NAV2_RADIO_SWAP",
+            // The BCD events of the COM radios cannot express 8.33 kHz channels
+            SimClientEvent::Com1StandbySet(_) => c"COM_STBY_RADIO_SET_HZ",
+            SimClientEvent::Com2StandbySet(_) => c"COM2_STBY_RADIO_SET_HZ",
+            SimClientEvent::Nav1StandbySet(_) => c"NAV1_STBY_SET",
+            SimClientEvent::Nav2StandbySet(_) => c"NAV2_STBY_SET",
+            SimClientEvent::TransponderSet(_) => c"XPNDR_SET",
+            SimClientEvent::TransponderIdent => c"XPNDR_IDENT_ON",
+            SimClientEvent::TransponderModeSet(_) => c"XPNDR_STATE_SET",
+            SimClientEvent::Named { name, .. } => name,
+        }
+    }
+}
+
 impl FlxClientEvent for SimClientEvent {
     fn event_id(&self) -> u32 {
-        *self as u32
+        if let SimClientEvent::Named { id, .. } = self {
+            return *id;
+        }
+        SimClientEvent::ALL
+            .iter()
+            .position(|event| mem::discriminant(event) == mem::discriminant(self))
+            .expect("event is listed in SimClientEvent::ALL") as u32
     }
 
     fn event_name(&self) -> *const std::ffi::c_char {
-        (match self {
-            SimClientEvent::LandingLightsOn => "LANDING_LIGHTS_ON\0",
-            SimClientEvent::LandingLightsOff => "LANDING_LIGHTS_OFF\0",
-            SimClientEvent::TaxiLightsOn => "TAXI_LIGHTS_ON\0",
-            SimClientEvent::TaxiLightsOff => "TAXI_LIGHTS_OFF\0",
-            SimClientEvent::StrobeLightsOn => "STROBES_ON\0",
-            SimClientEvent::StrobeLightsOff => "STROBES_OFF\0",
-            SimClientEvent::NavLightsOn => "NAV_LIGHTS_ON\0",
-            SimClientEvent::NavLightsOff => "NAV_LIGHTS_OFF\0",
-            SimClientEvent::FlapsUp => "FLAPS_DECR\0",
-            SimClientEvent::FlapsDown => "FLAPS_INCR\0",
-            SimClientEvent::ParkingBrakeOn => "PARKING_BRAKE_SET\0",
-            SimClientEvent::ParkingBrakeOff => "PARKING_BRAKE_SET\0",
-            SimClientEvent::LandingGearUp => "GEAR_UP\0",
-            SimClientEvent::LandingGearDown => "GEAR_DOWN\0",
-        })
-        .as_ptr() as *const std::ffi::c_char
+        self.name().as_ptr()
     }
 
     fn data(&self) -> u32 {
         match self {
             SimClientEvent::ParkingBrakeOn => 1,
             SimClientEvent::ParkingBrakeOff => 0,
+            SimClientEvent::LogoLightsOn => 1,
+            SimClientEvent::LogoLightsOff => 0,
+            SimClientEvent::RecognitionLightsOn => 1,
+            SimClientEvent::RecognitionLightsOff => 0,
+            SimClientEvent::FlapsSet(position) => *position,
+            // Negative axis values are passed in two's complement
+            SimClientEvent::ThrottleAxisSet(value)
+            | SimClientEvent::MixtureAxisSet(value)
+            | SimClientEvent::PropellerAxisSet(value)
+            | SimClientEvent::ElevatorTrimAxisSet(value)
+            | SimClientEvent::AutopilotVerticalSpeedSet(value) => *value as u32,
+            SimClientEvent::HeadingBugSet(value)
+            | SimClientEvent::KohlsmanSet(value)
+            | SimClientEvent::AutopilotAltitudeSet(value)
+            | SimClientEvent::AutopilotAirspeedSet(value) => *value,
+            SimClientEvent::Com1StandbySet(frequency)
+            | SimClientEvent::Com2StandbySet(frequency) => frequency.hz(),
+            SimClientEvent::Nav1StandbySet(frequency)
+            | SimClientEvent::Nav2StandbySet(frequency) => frequency.bcd16(),
+            SimClientEvent::TransponderSet(code) => bcd16_encode(*code),
+            // The modes are numbered in the order of TRANSPONDER STATE
+            SimClientEvent::TransponderModeSet(mode) => TransponderMode::ALL
+                .iter()
+                .position(|m| m == mode)
+                .expect("mode is listed in TransponderMode::ALL")
+                as u32,
+            SimClientEvent::Named { data, .. } => *data,
             _ => 0,
         }
     }
 }
 
+/// Request sent to the simulator, which later exceptions are attributed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Request {
+    /// Registration of a data definition, named by its contents.
+    Register(&'static str),
+    MapEvent(&'static CStr),
+    TransmitEvent(&'static CStr),
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Request::Register(name) => write!(f, "registering the {name}"),
+            Request::MapEvent(name) => write!(f, "mapping event {}", name.to_string_lossy()),
+            Request::TransmitEvent(name) => write!(f, "sending event {}", name.to_string_lossy()),
+        }
+    }
+}
+
+/// Problems reported by SimConnect during a connection, which are summarized when it ends.
+#[derive(Debug, Default)]
+struct SimStatus {
+    /// Number of exceptions by exception name and the request they are attributed to.
+    exceptions: HashMap<(&'static str, Option<Request>), u32>,
+    unknown_notifications: u32,
+}
+
 pub struct SimCommunicator {
     connected: bool,
-    sim_txs: Vec<mpsc::Sender<Event>>,
+    /// Panels that are sent the aircraft state when their SimVars change.
+    subscribers: Vec<Subscriber>,
     hw_rx: mpsc::Receiver<Event>,
+    profiles: ProfileSelector,
+    /// Aircraft data last received from the simulator.
+    aircraft_data: Option<AircraftSimData>,
+    /// L:vars of the aircraft profiles last received from the simulator.
+    lvars: Arc<HashMap<String, f64>>,
+    /// Request that was sent to the simulator last.
+    last_request: Option<Request>,
+    status: SimStatus,
 }
 
 impl SimCommunicator {
-    pub fn new(sim_txs: Vec<mpsc::Sender<Event>>, hw_rx: mpsc::Receiver<Event>) -> Self {
+    pub fn new(
+        subscribers: Vec<Subscriber>,
+        hw_rx: mpsc::Receiver<Event>,
+        profiles: ProfileSelector,
+    ) -> Self {
         Self {
             connected: false,
-            sim_txs,
+            subscribers,
             hw_rx,
+            profiles,
+            aircraft_data: None,
+            lvars: Arc::default(),
+            last_request: None,
+            status: SimStatus::default(),
         }
     }
 
@@ -165,6 +866,7 @@ impl SimCommunicator {
 
             // We are now disconnected
             self.connected = false;
+            self.report_status();
 
             // Wait before reconnecting
             std::thread::sleep(Duration::from_secs(5));
@@ -176,51 +878,81 @@ impl SimCommunicator {
             // Receive control messages if we are connected
             if self.connected {
                 match self.hw_rx.try_recv() {
-                    Ok(Event::SetSimulator(event)) => client.transmit_event(event)?,
+                    Ok(Event::SetSimulator(event)) => {
+                        self.last_request = Some(Request::TransmitEvent(event.name()));
+                        client.transmit_event(event)?
+                    }
                     Err(mpsc::TryRecvError::Disconnected) => return Ok(true),
                     _ => {}
                 }
             }
 
-            match client.get_next_dispatch()? {
+            let notification = match client.get_next_dispatch() {
+                Ok(notification) => notification,
+                Err(SimConnectError::SimConnectException(code)) => {
+                    self.handle_exception(code)?;
+                    None
+                }
+                Err(
+                    SimConnectError::UnimplementedMessageType(_)
+                    | SimConnectError::UnimplementedEventType(_),
+                ) => {
+                    self.status.unknown_notifications += 1;
+                    debug!("Ignoring unsupported SimConnect message");
+                    None
+                }
+                Err(e) => return Err(e),
+            };
+            match notification {
                 Some(Notification::Open) => {
                     info!("Connection with flight simulator established");
+                    self.aircraft_data = None;
+                    self.lvars = Arc::default();
                     // After the connection is successfully open, we register the aircraft data struct
+                    self.last_request = Some(Request::Register("aircraft SimVars"));
                     client.register_object::<AircraftSimData>()?;
+                    self.last_request = Some(Request::Register("aircraft TITLE and ATC MODEL"));
+                    client.register_object::<AircraftIdentity>()?;
+                    self.register_lvars(&client)?;
                     // We register the events we want to send to the simulator
-                    client.map_client_event_to_sim_event(SimClientEvent::LandingLightsOn)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::LandingLightsOff)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::TaxiLightsOn)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::TaxiLightsOff)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::StrobeLightsOn)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::StrobeLightsOff)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::NavLightsOn)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::NavLightsOff)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::FlapsUp)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::FlapsDown)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::ParkingBrakeOn)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::ParkingBrakeOff)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::LandingGearUp)?;
-                    client.map_client_event_to_sim_event(SimClientEvent::LandingGearDown)?;
+                    let events: Vec<SimClientEvent> = SimClientEvent::ALL
+                        .iter()
+                        .copied()
+                        .chain(self.profiles.events())
+                        .collect();
+                    for event in events {
+                        self.last_request = Some(Request::MapEvent(event.name()));
+                        client.map_client_event_to_sim_event(event)?;
+                    }
 
                     // We are now successfully connected
                     self.connected = true;
+                    for subscriber in &mut self.subscribers {
+                        subscriber.send(Event::SimConnected);
+                    }
                 }
                 Some(Notification::Quit) => {
                     info!("Disconnected from flight simulator");
                     return Ok(false);
                 }
+                Some(Notification::Object(data)) if data.id == LVARS_REQUEST_ID => {
+                    self.lvars = Arc::new(self.read_lvars(&data));
+                    debug!("Received SimConnect L:vars {:?}", self.lvars);
+                    self.send_state();
+                }
                 Some(Notification::Object(data)) => {
-                    let aircraft_state = AircraftSimData::try_from(&data)?;
-                    debug!("Received SimConnect aircraft state {:?}", aircraft_state);
-                    for sim_tx in &self.sim_txs {
-                        sim_tx
-                            .send(Event::SetPanel(aircraft_state.clone().into()))
-                            .expect("Failed to send to panel");
+                    if let Ok(aircraft) = AircraftIdentity::try_from(&data) {
+                        self.select_profile(&aircraft);
+                    } else {
+                        let aircraft_data = AircraftSimData::try_from(&data)?;
+                        debug!("Received SimConnect aircraft state {:?}", aircraft_data);
+                        self.aircraft_data = Some(aircraft_data);
+                        self.send_state();
                     }
                 }
-                Some(unkn) => {
-                    dbg!(unkn);
+                Some(notification) => {
+                    self.status.unknown_notifications += 1;
+                    debug!("Ignoring SimConnect notification {notification:?}");
                 }
                 _ => {}
             }
@@ -229,4 +961,163 @@ impl SimCommunicator {
             std::thread::sleep(std::time::Duration::from_millis(10));
         }
     }
+
+    /// Request the L:vars of the aircraft profiles, which add-on aircraft use instead of the standard SimVars.
+    ///
+    /// The L:vars are written through the simulator events of the profiles, e.g. the `MobiFlight.<preset>` events of
+    /// the MobiFlight WASM module, which also fire H:events.
+    fn register_lvars(&mut self, client: &SimConnect) -> Result<(), SimConnectError> {
+        if self.profiles.lvars().is_empty() {
+            return Ok(());
+        }
+        self.last_request = Some(Request::Register("L:vars of the aircraft profiles"));
+        for name in self.profiles.lvars() {
+            client.add_to_data_definition(LVARS_REQUEST_ID, name, "number", DataType::Float64)?;
+        }
+        client.request_data_on_sim_object(LVARS_REQUEST_ID, Period::SimFrame, Condition::Changed, 0)
+    }
+
+    /// Values of the L:vars in the order they were added to the data definition.
+    fn read_lvars(&self, data: &Object) -> HashMap<String, f64> {
+        let values = data.data_addr as *const f64;
+        self.profiles
+            .lvars()
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                // SAFETY: The simulator sends one float per L:var of the data definition
+                let value = unsafe { values.add(index).read_unaligned() };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    /// Pass the aircraft state with the L:vars to the panels whose SimVars changed.
+    fn send_state(&mut self) {
+        let Some(aircraft_data) = &self.aircraft_data else {
+            return;
+        };
+        let mut state: AircraftSimState = aircraft_data.clone().into();
+        state.lvars = self.lvars.clone();
+        for subscriber in &mut self.subscribers {
+            subscriber.update(&state);
+        }
+    }
+
+    /// Warn about an exception SimConnect reported for one of our requests, keeping the connection if possible.
+    ///
+    /// The SDK does not pass on which request failed, so the exception is attributed to the request sent last. As
+    /// the simulator answers asynchronously, this is only a hint when many requests were sent at once.
+    fn handle_exception(&mut self, code: u32) -> Result<(), SimConnectError> {
+        let name = EXCEPTION_NAMES
+            .get(code as usize)
+            .copied()
+            .unwrap_or("UNKNOWN");
+        if FATAL_EXCEPTIONS.contains(&name) {
+            return Err(SimConnectError::SimConnectException(code));
+        }
+
+        let count = self
+            .status
+            .exceptions
+            .entry((name, self.last_request))
+            .or_default();
+        *count += 1;
+        // Repeated exceptions, e.g. for an event sent with every switch flip, are only warned about once
+        let cause = match self.last_request {
+            Some(request) => format!(" after {request}"),
+            None => String::new(),
+        };
+        if *count == 1 {
+            warn!("SimConnect exception {name} ({code}){cause}");
+        } else {
+            debug!("SimConnect exception {name} ({code}){cause}, {count} times");
+        }
+        Ok(())
+    }
+
+    /// Summarize the problems SimConnect reported during the connection that ended.
+    fn report_status(&mut self) {
+        let status = mem::take(&mut self.status);
+        let exceptions: u32 = status.exceptions.values().sum();
+        if exceptions == 0 && status.unknown_notifications == 0 {
+            return;
+        }
+        warn!(
+            "SimConnect reported {exceptions} exceptions and {} unknown notifications during the connection",
+            status.unknown_notifications
+        );
+        for ((name, request), count) in status.exceptions {
+            match request {
+                Some(request) => info!("  {count} x {name} after {request}"),
+                None => info!("  {count} x {name}"),
+            }
+        }
+    }
+
+    /// Select the aircraft profile for the loaded aircraft and pass it to the panels.
+    fn select_profile(&mut self, aircraft: &AircraftIdentity) {
+        let profile = self.profiles.select(&aircraft.title, &aircraft.atc_model);
+        match &profile {
+            Some(profile) => info!(
+                "Using aircraft profile '{}' for '{}'",
+                profile.name, aircraft.title
+            ),
+            None => info!(
+                "No aircraft profile for '{}' ({}), using the panel configuration",
+                aircraft.title, aircraft.atc_model
+            ),
+        }
+        for subscriber in &mut self.subscribers {
+            subscriber.send(Event::SetProfile(profile.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Notification for a dispatch buffer with one float per SimVar in the order of the data definition.
+    fn object(buffer: &[u8]) -> Object {
+        Object {
+            id: 0,
+            data_addr: buffer.as_ptr() as *const u32,
+        }
+    }
+
+    #[test]
+    fn aircraft_sim_data_reads_fields_in_order() {
+        let buffer: Vec<u8> = (0..AIRCRAFT_SIM_DATA_FIELDS)
+            .flat_map(|index| (index as f64).to_le_bytes())
+            .collect();
+        let data = AircraftSimData::try_from(&object(&buffer)).unwrap();
+
+        assert_eq!(data.gear_center_position, 0.0);
+        assert_eq!(data.airspeed, 3.0);
+        assert_eq!(data.gear_handle_position, 39.0);
+        assert_eq!(data.parking_brake_indicator, 40.0);
+        assert_eq!(data.light_cabin, 50.0);
+    }
+
+    #[test]
+    fn aircraft_sim_state_converts_bool_sim_vars() {
+        let mut values = [0.0f64; AIRCRAFT_SIM_DATA_FIELDS];
+        // Gear down and the parking brake set, followed by the cabin light as the last field
+        values[..3].fill(1.0);
+        values[40] = 1.0;
+        values[50] = 1.0;
+        let buffer: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let state: AircraftSimState = AircraftSimData::try_from(&object(&buffer)).unwrap().into();
+
+        assert_eq!(state.gear_center_state, LandingGearStatus::Down);
+        assert_eq!(state.gear_left_state, LandingGearStatus::Down);
+        assert_eq!(state.gear_right_state, LandingGearStatus::Down);
+        assert!(state.parking_brake_indicator);
+        assert!(state.lights.cabin);
+        assert!(!state.lights.landing);
+    }
 }
@@ -0,0 +1,293 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::mpsc;
+
+use crate::sim::AircraftSimState;
+use crate::Event;
+
+/// Simulator values a panel subscribes to, whose changes are detected individually.
+///
+/// Related SimVars that panels always use together, like the frequencies of the radio stack, form a single variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SimVar {
+    Gear,
+    ParkingBrake,
+    Lights,
+    Flaps,
+    Airspeed,
+    IndicatedAltitude,
+    AltitudeAboveGround,
+    OnGround,
+    VerticalSpeed,
+    Heading,
+    TurnRate,
+    SlipBall,
+    Pitch,
+    Bank,
+    HeadingBug,
+    Kohlsman,
+    Autopilot,
+    Radios,
+    Transponder,
+    /// L:vars read for the aircraft profiles.
+    Lvars,
+}
+
+/// Typed value of a SimVar, which decides how changes are detected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimValue {
+    Bool(bool),
+    /// Discrete state like the position of a gear leg, which changes with every difference.
+    Enum(u32),
+    /// Continuous value, which changes once it differs by more than the epsilon of its variable.
+    Float(f64),
+}
+
+impl SimVar {
+    /// Default of the smallest difference of the float values that counts as a change, below the resolution of any
+    /// display. The `[epsilons]` of the configuration replace it per variable.
+    pub fn epsilon(&self) -> f64 {
+        match self {
+            SimVar::Airspeed => 0.05,
+            SimVar::IndicatedAltitude => 0.5,
+            SimVar::AltitudeAboveGround => 1.0,
+            SimVar::VerticalSpeed => 1.0,
+            SimVar::Heading | SimVar::Pitch | SimVar::Bank => 0.05,
+            SimVar::TurnRate => 0.01,
+            SimVar::SlipBall => 0.1,
+            SimVar::HeadingBug => 0.5,
+            SimVar::Kohlsman => 0.001,
+            SimVar::Autopilot => 0.5,
+            _ => 0.0,
+        }
+    }
+
+    /// Typed values of the variable in the state.
+    pub fn values(&self, state: &AircraftSimState) -> Vec<SimValue> {
+        use SimValue::{Bool, Enum, Float};
+
+        match self {
+            SimVar::Gear => vec![
+                Enum(state.gear_center_state as u32),
+                Enum(state.gear_left_state as u32),
+                Enum(state.gear_right_state as u32),
+                Bool(state.gear_retractable),
+                Bool(state.gear_handle_down),
+            ],
+            SimVar::ParkingBrake => vec![Bool(state.parking_brake_indicator)],
+            SimVar::Lights => {
+                let lights = &state.lights;
+                [
+                    lights.landing,
+                    lights.taxi,
+                    lights.strobe,
+                    lights.nav,
+                    lights.beacon,
+                    lights.wing,
+                    lights.logo,
+                    lights.recognition,
+                    lights.panel,
+                    lights.cabin,
+                ]
+                .into_iter()
+                .map(Bool)
+                .collect()
+            }
+            SimVar::Flaps => vec![
+                Enum(state.flaps_handle_index),
+                Enum(state.flaps_handle_positions),
+            ],
+            SimVar::Airspeed => vec![Float(state.airspeed)],
+            SimVar::IndicatedAltitude => vec![Float(state.indicated_altitude)],
+            SimVar::AltitudeAboveGround => vec![Float(state.altitude_above_ground)],
+            SimVar::OnGround => vec![Bool(state.on_ground)],
+            SimVar::VerticalSpeed => vec![Float(state.vertical_speed)],
+            SimVar::Heading => vec![Float(state.heading_indicator)],
+            SimVar::TurnRate => vec![Float(state.turn_rate)],
+            SimVar::SlipBall => vec![Float(state.turn_coordinator_ball)],
+            SimVar::Pitch => vec![Float(state.attitude_pitch)],
+            SimVar::Bank => vec![Float(state.attitude_bank)],
+            SimVar::HeadingBug => vec![Float(state.heading_bug)],
+            SimVar::Kohlsman => vec![Float(state.kohlsman_setting)],
+            SimVar::Autopilot => {
+                let autopilot = &state.autopilot;
+                vec![
+                    Bool(autopilot.master),
+                    Bool(autopilot.heading_lock),
+                    Bool(autopilot.altitude_lock),
+                    Bool(autopilot.vertical_hold),
+                    Bool(autopilot.airspeed_hold),
+                    Bool(autopilot.nav1_lock),
+                    Bool(autopilot.approach_hold),
+                    Float(autopilot.altitude),
+                    Float(autopilot.vertical_speed),
+                    Float(autopilot.airspeed),
+                ]
+            }
+            SimVar::Radios => {
+                let radios = &state.radios;
+                [
+                    radios.com1_active,
+                    radios.com1_standby,
+                    radios.com2_active,
+                    radios.com2_standby,
+                    radios.nav1_active,
+                    radios.nav1_standby,
+                    radios.nav2_active,
+                    radios.nav2_standby,
+                ]
+                .into_iter()
+                .map(|frequency| Enum(frequency.hz()))
+                .collect()
+            }
+            SimVar::Transponder => vec![
+                Enum(state.transponder.code),
+                Enum(state.transponder.mode as u32),
+                Bool(state.transponder.ident),
+            ],
+            SimVar::Lvars => {
+                let mut lvars: Vec<(&String, &f64)> = state.lvars.iter().collect();
+                lvars.sort_by_key(|(name, _)| *name);
+                lvars.into_iter().map(|(_, value)| Float(*value)).collect()
+            }
+        }
+    }
+
+    /// Whether the variable changed between two states, where float values have to differ by more than `epsilon`.
+    pub fn changed(&self, old: &AircraftSimState, new: &AircraftSimState, epsilon: f64) -> bool {
+        let (old, new) = (self.values(old), self.values(new));
+        old.len() != new.len()
+            || old.iter().zip(&new).any(|values| match values {
+                (SimValue::Float(old), SimValue::Float(new)) => (new - old).abs() > epsilon,
+                (old, new) => old != new,
+            })
+    }
+}
+
+/// Panel thread that is sent the aircraft state whenever one of its SimVars changed.
+#[derive(Debug)]
+pub struct Subscriber {
+    tx: mpsc::Sender<Event>,
+    /// SimVars of the panel with their epsilon.
+    sim_vars: Vec<(SimVar, f64)>,
+    /// State that was last sent to the panel, which changes are detected against.
+    sent: Option<AircraftSimState>,
+}
+
+impl Subscriber {
+    /// Subscriber to the SimVars, using the configured `epsilons` instead of the defaults where there are any.
+    pub fn new(
+        tx: mpsc::Sender<Event>,
+        sim_vars: Vec<SimVar>,
+        epsilons: &HashMap<SimVar, f64>,
+    ) -> Self {
+        let sim_vars = sim_vars
+            .into_iter()
+            .map(|sim_var| {
+                let epsilon = epsilons.get(&sim_var).copied();
+                (sim_var, epsilon.unwrap_or_else(|| sim_var.epsilon()))
+            })
+            .collect();
+        Self {
+            tx,
+            sim_vars,
+            sent: None,
+        }
+    }
+
+    /// Send the state if one of the SimVars of the panel changed since the state that was last sent.
+    ///
+    /// Changes are detected against the state that was sent and not the previous one, so that slow drifts below the
+    /// epsilon of a SimVar are eventually passed on.
+    pub fn update(&mut self, state: &AircraftSimState) {
+        let changed = self.sent.as_ref().is_none_or(|sent| {
+            self.sim_vars
+                .iter()
+                .any(|(sim_var, epsilon)| sim_var.changed(sent, state, *epsilon))
+        });
+        if changed {
+            self.sent = Some(state.clone());
+            self.send(Event::SetPanel(state.clone()));
+        }
+    }
+
+    /// Send an event to the panel regardless of its SimVars, forgetting the sent state after a reconnect.
+    pub fn send(&mut self, event: Event) {
+        if let Event::SimConnected = event {
+            self.sent = None;
+        }
+        self.tx.send(event).expect("Failed to send to panel");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::LandingGearStatus;
+
+    fn subscriber(
+        sim_vars: &[SimVar],
+        epsilons: &[(SimVar, f64)],
+    ) -> (Subscriber, mpsc::Receiver<Event>) {
+        let (tx, rx) = mpsc::channel();
+        let epsilons = epsilons.iter().copied().collect();
+        (Subscriber::new(tx, sim_vars.to_vec(), &epsilons), rx)
+    }
+
+    fn airspeed(airspeed: f64) -> AircraftSimState {
+        AircraftSimState {
+            airspeed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn float_changes_below_epsilon_do_not_notify() {
+        let (mut subscriber, rx) = subscriber(&[SimVar::Airspeed], &[]);
+
+        subscriber.update(&airspeed(100.0));
+        subscriber.update(&airspeed(100.03));
+        assert_eq!(rx.try_iter().count(), 1);
+
+        // Changes add up against the state that was sent last
+        subscriber.update(&airspeed(100.06));
+        assert_eq!(rx.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn configured_epsilon_replaces_default() {
+        let (mut subscriber, rx) = subscriber(&[SimVar::Airspeed], &[(SimVar::Airspeed, 1.0)]);
+
+        subscriber.update(&airspeed(100.0));
+        subscriber.update(&airspeed(100.5));
+        assert_eq!(rx.try_iter().count(), 1);
+        subscriber.update(&airspeed(101.5));
+        assert_eq!(rx.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn bool_and_enum_changes_notify() {
+        let (mut subscriber, rx) = subscriber(&[SimVar::ParkingBrake, SimVar::Gear], &[]);
+        let mut state = AircraftSimState::default();
+        subscriber.update(&state);
+        assert_eq!(rx.try_iter().count(), 1);
+
+        state.parking_brake_indicator = true;
+        subscriber.update(&state);
+        assert_eq!(rx.try_iter().count(), 1);
+
+        state.gear_left_state = LandingGearStatus::InTransit;
+        subscriber.update(&state);
+        assert_eq!(rx.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn only_subscribed_sim_vars_notify() {
+        let (mut subscriber, rx) = subscriber(&[SimVar::Gear], &[]);
+
+        subscriber.update(&airspeed(100.0));
+        subscriber.update(&airspeed(150.0));
+        assert_eq!(rx.try_iter().count(), 1);
+    }
+}